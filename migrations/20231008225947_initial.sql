@@ -1,9 +1,12 @@
-CREATE TYPE status AS ENUM ('ready', 'in-progress', 'failed');
+-- Object names are templated: `{{name}}` expands to the name prefixed and qualified according to the `QueueConfig`.
+CREATE SCHEMA IF NOT EXISTS {{schema}};
 
-CREATE TABLE queue (
+CREATE TYPE {{status}} AS ENUM ('ready', 'in-progress', 'failed');
+
+CREATE TABLE {{queue}} (
     id BIGSERIAL PRIMARY KEY,
-    status status NOT NULL DEFAULT 'ready',
+    status {{status}} NOT NULL DEFAULT 'ready',
     item JSONB NOT NULL,
     -- Error message in case of permanent failure. If set, status MUST be 'failed'.
-    message TEXT CHECK ((message == NULL && status != 'failed') OR (message != NULL && status == 'failed')) ,
-);
\ No newline at end of file
+    message TEXT CHECK ((message IS NULL AND status != 'failed') OR (message IS NOT NULL AND status = 'failed'))
+);
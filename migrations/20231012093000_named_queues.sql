@@ -0,0 +1,5 @@
+-- Multiple logical queues share the table, identified by name.
+ALTER TABLE {{queue}} ADD COLUMN queue TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE {{queue}} ALTER COLUMN queue DROP DEFAULT;
+
+CREATE INDEX ON {{queue}} (queue, status, id);
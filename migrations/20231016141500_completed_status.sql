@@ -0,0 +1,12 @@
+-- Successfully processed items are either kept as 'completed', deleted or moved to the archive, depending on the
+-- queue's `CompletionPolicy`.
+ALTER TYPE {{status}} ADD VALUE 'completed';
+
+ALTER TABLE {{queue}} ADD COLUMN completed_at TIMESTAMPTZ;
+
+CREATE TABLE {{queue_archive}} (
+    id BIGINT PRIMARY KEY,
+    queue TEXT NOT NULL,
+    item JSONB NOT NULL,
+    completed_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Items dequeued without a processing transaction are leased until `locked_until`. Once the lease expires, they can
+-- be dequeued again.
+ALTER TABLE {{queue}} ADD COLUMN locked_until TIMESTAMPTZ;
+-- Number of times the item was dequeued. Identifies the lease of an in-progress item, so that a worker whose lease
+-- expired cannot settle the item once it was dequeued again.
+ALTER TABLE {{queue}} ADD COLUMN attempts INT NOT NULL DEFAULT 0;
+
+-- Finds in-progress items whose lease expired, which are dequeued again.
+CREATE INDEX ON {{queue}} (queue, locked_until) WHERE status = 'in-progress';
@@ -0,0 +1,2 @@
+-- Once `attempts` reaches `max_attempts`, the item is marked as failed instead of being requeued.
+ALTER TABLE {{queue}} ADD COLUMN max_attempts INT NOT NULL DEFAULT 5;
@@ -0,0 +1,2 @@
+-- Requeued items are not dequeued before `run_after`, according to the queue's `RetryPolicy`.
+ALTER TABLE {{queue}} ADD COLUMN run_after TIMESTAMPTZ NOT NULL DEFAULT now();
@@ -0,0 +1,5 @@
+-- The time an item was scheduled for when it was enqueued. Items are dequeued in order of `run_after`, which starts at
+-- `scheduled_at` and is pushed back by retries.
+ALTER TABLE {{queue}} ADD COLUMN scheduled_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE INDEX {{local queue_ready_idx}} ON {{queue}} (queue, run_after, id) WHERE status = 'ready';
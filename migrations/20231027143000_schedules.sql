@@ -0,0 +1,18 @@
+-- Recurring items, enqueued into `queue` whenever `cron` fires. See `Scheduler`.
+CREATE TYPE {{misfire}} AS ENUM ('skip', 'run-once', 'run-all');
+
+CREATE TABLE {{schedules}} (
+    queue TEXT NOT NULL,
+    name TEXT NOT NULL,
+    cron TEXT NOT NULL,
+    -- IANA name of the timezone in which `cron` is evaluated.
+    timezone TEXT NOT NULL,
+    item JSONB NOT NULL,
+    max_attempts INT NOT NULL,
+    misfire {{misfire}} NOT NULL,
+    next_run_at TIMESTAMPTZ NOT NULL,
+    -- Every queue has its own namespace of schedule names.
+    PRIMARY KEY (queue, name)
+);
+
+CREATE INDEX ON {{schedules}} (next_run_at);
@@ -0,0 +1,8 @@
+-- Items with a higher priority are dequeued first. See `Queue::with_priority` and `Queue::with_aging`.
+ALTER TABLE {{queue}} ADD COLUMN priority INT NOT NULL DEFAULT 0;
+ALTER TABLE {{schedules}} ADD COLUMN priority INT NOT NULL DEFAULT 0;
+
+-- Replaces the index on (queue, run_after, id) created for scheduled items.
+DROP INDEX {{queue_ready_idx}};
+CREATE INDEX {{local queue_ready_priority_idx}} ON {{queue}} (queue, priority DESC, run_after, id)
+WHERE status = 'ready';
@@ -0,0 +1,2 @@
+-- Listeners look up when the next ready item of their queue is due on every wakeup. See `Listener`.
+CREATE INDEX {{local queue_next_due_idx}} ON {{queue}} (queue, run_after) WHERE status = 'ready';
@@ -0,0 +1,8 @@
+-- Identifies the type of an item, for routing it to the handler registered for its kind. See `Registry`.
+ALTER TABLE {{queue}} ADD COLUMN kind TEXT;
+ALTER TABLE {{queue_archive}} ADD COLUMN kind TEXT;
+
+-- Queues of a `Job` only dequeue items of their kind, which they find without scanning the items of other kinds
+-- sharing the queue name.
+CREATE INDEX {{local queue_ready_kind_idx}} ON {{queue}} (queue, kind, priority DESC, run_after, id)
+WHERE status = 'ready';
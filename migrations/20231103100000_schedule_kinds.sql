@@ -0,0 +1,2 @@
+-- The kind of the items enqueued by a schedule, so that scheduled jobs can be routed by a `Registry`.
+ALTER TABLE {{schedules}} ADD COLUMN kind TEXT;
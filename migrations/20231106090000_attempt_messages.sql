@@ -0,0 +1,5 @@
+-- Items that are retried keep the message of their last failed attempt, e.g. the payload of a panic. Failed items
+-- still always have a message. The check replaced here is unnamed in the initial migration, so it carries the name
+-- Postgres gives to table checks.
+ALTER TABLE {{queue}} DROP CONSTRAINT {{local queue_check}};
+ALTER TABLE {{queue}} ADD CONSTRAINT {{local queue_message_check}} CHECK (message IS NOT NULL OR status != 'failed');
@@ -0,0 +1,4 @@
+-- How long the handler may run on the item before the attempt is abandoned. See `EnqueueOptions::timeout`.
+ALTER TABLE {{queue}} ADD COLUMN timeout INTERVAL;
+-- The timeout of the items enqueued by a schedule.
+ALTER TABLE {{schedules}} ADD COLUMN timeout INTERVAL;
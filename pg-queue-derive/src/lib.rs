@@ -0,0 +1,188 @@
+//! `#[derive(Job)]` for `pg-queue`. Use it through the `derive` feature of `pg-queue` rather than directly.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::meta::ParseNestedMeta;
+use syn::parse::Parse;
+use syn::{parse_macro_input, DeriveInput, Expr, LitStr};
+
+/// Implements `pg_queue::Job`, taking the job's constants from `#[job(...)]` attributes:
+///
+/// - `kind = "..."`, defaulting to the name of the type.
+/// - `queue = "..."`, defaulting to `"default"`.
+/// - `priority = <expr>`, `max_attempts = <expr>`, `retry = <expr>` and `timeout = <expr>`, defaulting to those of
+///   the queue.
+#[proc_macro_derive(Job, attributes(job))]
+pub fn derive_job(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct Attributes {
+    kind: Option<LitStr>,
+    queue: Option<LitStr>,
+    priority: Option<Expr>,
+    max_attempts: Option<Expr>,
+    retry: Option<Expr>,
+    timeout: Option<Expr>,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut attributes = Attributes::default();
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("job"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("kind") {
+                set(&mut attributes.kind, &meta)
+            } else if meta.path.is_ident("queue") {
+                set(&mut attributes.queue, &meta)
+            } else if meta.path.is_ident("priority") {
+                set(&mut attributes.priority, &meta)
+            } else if meta.path.is_ident("max_attempts") {
+                set(&mut attributes.max_attempts, &meta)
+            } else if meta.path.is_ident("retry") {
+                set(&mut attributes.retry, &meta)
+            } else if meta.path.is_ident("timeout") {
+                set(&mut attributes.timeout, &meta)
+            } else {
+                Err(meta.error(
+                    "unknown job attribute, expected `kind`, `queue`, `priority`, `max_attempts`, `retry` or \
+                     `timeout`",
+                ))
+            }
+        })?;
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let kind = attributes
+        .kind
+        .unwrap_or_else(|| LitStr::new(&name.to_string(), name.span()));
+    let queue = attributes.queue.map(|queue| {
+        quote! { const QUEUE: &'static str = #queue; }
+    });
+    let priority = attributes.priority.map(|priority| {
+        quote! { const PRIORITY: ::core::option::Option<i32> = ::core::option::Option::Some(#priority); }
+    });
+    let max_attempts = attributes.max_attempts.map(|max_attempts| {
+        quote! { const MAX_ATTEMPTS: ::core::option::Option<i32> = ::core::option::Option::Some(#max_attempts); }
+    });
+    let retry = attributes.retry.map(|retry| {
+        quote! {
+            const RETRY: ::core::option::Option<::pg_queue::RetryPolicy> = ::core::option::Option::Some(#retry);
+        }
+    });
+
+    let timeout = attributes.timeout.map(|timeout| {
+        quote! {
+            const TIMEOUT: ::core::option::Option<::core::time::Duration> = ::core::option::Option::Some(#timeout);
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::pg_queue::Job for #name #ty_generics #where_clause {
+            const KIND: &'static str = #kind;
+            #queue
+            #priority
+            #max_attempts
+            #retry
+            #timeout
+        }
+    })
+}
+
+/// Parses the value of an attribute, which may only be given once.
+fn set<T: Parse>(slot: &mut Option<T>, meta: &ParseNestedMeta) -> syn::Result<()> {
+    if slot.is_some() {
+        return Err(meta.error("duplicate job attribute"));
+    }
+    *slot = Some(meta.value()?.parse()?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn expanded(input: DeriveInput) -> String {
+        expand(input).unwrap().to_string()
+    }
+
+    fn error(input: DeriveInput) -> String {
+        expand(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn defaults() {
+        let expected = quote! {
+            impl ::pg_queue::Job for SendEmail {
+                const KIND: &'static str = "SendEmail";
+            }
+        };
+        assert_eq!(
+            expanded(parse_quote! { struct SendEmail; }),
+            expected.to_string()
+        );
+    }
+
+    #[test]
+    fn attributes() {
+        let expected = quote! {
+            impl ::pg_queue::Job for SendEmail {
+                const KIND: &'static str = "email";
+                const QUEUE: &'static str = "mail";
+                const PRIORITY: ::core::option::Option<i32> = ::core::option::Option::Some(10);
+                const MAX_ATTEMPTS: ::core::option::Option<i32> = ::core::option::Option::Some(3);
+                const RETRY: ::core::option::Option<::pg_queue::RetryPolicy> =
+                    ::core::option::Option::Some(RetryPolicy::Fixed(Duration::ZERO));
+                const TIMEOUT: ::core::option::Option<::core::time::Duration> =
+                    ::core::option::Option::Some(Duration::from_secs(30));
+            }
+        };
+        let input = parse_quote! {
+            #[job(kind = "email", queue = "mail", priority = 10)]
+            #[job(max_attempts = 3, retry = RetryPolicy::Fixed(Duration::ZERO), timeout = Duration::from_secs(30))]
+            struct SendEmail;
+        };
+        assert_eq!(expanded(input), expected.to_string());
+    }
+
+    #[test]
+    fn generics() {
+        let expected = quote! {
+            impl<T: Send> ::pg_queue::Job for Wrapper<T> where T: Clone {
+                const KIND: &'static str = "Wrapper";
+            }
+        };
+        let input = parse_quote! { struct Wrapper<T: Send>(T) where T: Clone; };
+        assert_eq!(expanded(input), expected.to_string());
+    }
+
+    #[test]
+    fn rejects_unknown_attributes() {
+        let message = error(parse_quote! { #[job(name = "email")] struct SendEmail; });
+        assert!(message.starts_with("unknown job attribute"), "{message}");
+    }
+
+    #[test]
+    fn rejects_duplicate_attributes() {
+        let input = parse_quote! {
+            #[job(kind = "a")]
+            #[job(kind = "b")]
+            struct SendEmail;
+        };
+        assert_eq!(error(input), "duplicate job attribute");
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert!(expand(parse_quote! { #[job(kind = email)] struct SendEmail; }).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+/// Where the queue stores its tables and types.
+///
+/// Every object is created in `schema`, with `prefix` prepended to its name. The default configuration uses the
+/// `public` schema without a prefix, meaning that the queue table is `public.queue`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueConfig {
+    /// Postgres schema holding the queue objects. The migrations create it if it does not exist yet.
+    pub schema: String,
+    /// Prepended to the name of every table and type, e.g. `jobs_` turns the `queue` table into `jobs_queue`.
+    pub prefix: String,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            schema: "public".to_owned(),
+            prefix: String::new(),
+        }
+    }
+}
+
+impl QueueConfig {
+    /// The qualified and quoted name of the object `name`, e.g. `"public"."queue"`.
+    pub(crate) fn ident(&self, name: &str) -> String {
+        format!(
+            "{}.{}",
+            quote(&self.schema),
+            quote(&format!("{}{}", self.prefix, name))
+        )
+    }
+
+    /// Identifies the queue `name` in notifications. Notifications are sent on the channel `'pg_queue_' || md5(key)`,
+    /// which stays within the length limit of channel names regardless of the schema, prefix and queue name.
+    pub(crate) fn channel_key(&self, name: &str) -> String {
+        format!("{}:{}", self.ident("queue"), name)
+    }
+
+    /// Expands the `{{name}}` placeholders in `sql` to qualified identifiers (see [`QueueConfig::ident`]).
+    /// `{{schema}}` expands to the quoted schema itself, and `{{local name}}` to the prefixed name without the schema,
+    /// for statements that only accept unqualified names such as `CREATE INDEX`.
+    pub(crate) fn render(&self, sql: &str) -> String {
+        let mut rendered = String::with_capacity(sql.len());
+        let mut rest = sql;
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start..].find("}}") else {
+                break;
+            };
+            let name = &rest[start + 2..start + len];
+            rendered.push_str(&rest[..start]);
+            if name == "schema" {
+                rendered.push_str(&quote(&self.schema));
+            } else if let Some(name) = name.strip_prefix("local ") {
+                rendered.push_str(&quote(&format!("{}{}", self.prefix, name)));
+            } else {
+                rendered.push_str(&self.ident(name));
+            }
+            rest = &rest[start + len + 2..];
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+fn quote(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes() {
+        assert_eq!(quote("queue"), r#""queue""#);
+        assert_eq!(quote(r#"my "queue""#), r#""my ""queue""""#);
+    }
+
+    #[test]
+    fn renders_default() {
+        let config = QueueConfig::default();
+        assert_eq!(
+            config.render("SELECT * FROM {{queue}} WHERE status = 'ready'"),
+            r#"SELECT * FROM "public"."queue" WHERE status = 'ready'"#
+        );
+    }
+
+    #[test]
+    fn renders_schema_and_prefix() {
+        let config = QueueConfig {
+            schema: "jobs".to_owned(),
+            prefix: "pg_".to_owned(),
+        };
+        assert_eq!(
+            config.render("CREATE SCHEMA {{schema}}; DROP TYPE {{status}}; ALTER TABLE {{queue}}"),
+            r#"CREATE SCHEMA "jobs"; DROP TYPE "jobs"."pg_status"; ALTER TABLE "jobs"."pg_queue""#
+        );
+        assert_eq!(
+            config.render("CREATE INDEX {{local queue_ready_idx}} ON {{queue}} (id)"),
+            r#"CREATE INDEX "pg_queue_ready_idx" ON "jobs"."pg_queue" (id)"#
+        );
+    }
+
+    #[test]
+    fn renders_quoted_names() {
+        let config = QueueConfig {
+            schema: r#"my "schema""#.to_owned(),
+            prefix: r#"a"b_"#.to_owned(),
+        };
+        assert_eq!(
+            config.render("{{queue}}"),
+            r#""my ""schema"""."a""b_queue""#
+        );
+    }
+
+    #[test]
+    fn leaves_other_text() {
+        let config = QueueConfig::default();
+        assert_eq!(
+            config.render("SELECT '{', '}}', $1"),
+            "SELECT '{', '}}', $1"
+        );
+        assert_eq!(config.render("SELECT {{queue"), "SELECT {{queue");
+    }
+}
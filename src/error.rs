@@ -0,0 +1,73 @@
+use std::fmt;
+
+use sqlx::migrate::MigrateError;
+
+/// Errors returned by the queue.
+#[derive(Debug)]
+pub enum Error {
+    /// A query failed, or the database could not be reached.
+    Database(sqlx::Error),
+    /// An item could not be serialized to JSON.
+    Serialization(serde_json::Error),
+    /// The handler failed to process an item, which was requeued or marked as failed according to the message.
+    Handler(String),
+    /// The database schema does not match this version of the crate, for example because [`migrate`] was not run, or
+    /// was run by a newer version.
+    ///
+    /// [`migrate`]: crate::migrate
+    SchemaMismatch(String),
+    /// A [`Schedule`](crate::Schedule) or [`Scheduler`](crate::Scheduler) is invalid, e.g. because of a malformed
+    /// cron expression.
+    InvalidSchedule(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Database(error) => write!(f, "database error: {error}"),
+            Self::Serialization(error) => write!(f, "unable to serialize item: {error}"),
+            Self::Handler(message) => write!(f, "processing failed, {message}"),
+            Self::SchemaMismatch(message) => write!(f, "schema mismatch: {message}"),
+            Self::InvalidSchedule(message) => write!(f, "invalid schedule: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Database(error) => Some(error),
+            Self::Serialization(error) => Some(error),
+            Self::Handler(_) | Self::SchemaMismatch(_) | Self::InvalidSchedule(_) => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(error: sqlx::Error) -> Self {
+        // undefined_table, undefined_column and undefined_object: the migrations have not been applied.
+        match &error {
+            sqlx::Error::Database(db)
+                if matches!(db.code().as_deref(), Some("42P01" | "42703" | "42704")) =>
+            {
+                Self::SchemaMismatch(db.message().to_owned())
+            }
+            _ => Self::Database(error),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Serialization(error)
+    }
+}
+
+impl From<MigrateError> for Error {
+    fn from(error: MigrateError) -> Self {
+        match error {
+            MigrateError::Execute(error) => error.into(),
+            error => Self::SchemaMismatch(error.to_string()),
+        }
+    }
+}
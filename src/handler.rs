@@ -0,0 +1,220 @@
+use std::any::Any;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use sqlx::{Postgres, Transaction};
+
+use crate::{ProcessFlow, RetryPolicy};
+
+/// Processes items dequeued by [`Queue::process`](crate::Queue::process).
+///
+/// The handler receives the transaction holding the lock on the item. Anything written through it commits or rolls
+/// back together with the item's new state, so database side effects happen exactly once.
+pub trait Handler<T> {
+    fn handle<'a>(
+        self,
+        tx: &'a mut Transaction<'_, Postgres>,
+        item: T,
+    ) -> BoxFuture<'a, Result<ProcessFlow, ()>>;
+
+    /// Overrides the timeout of the items' [`Job`](crate::Job) and queue, set by
+    /// [`TimeoutLayer`](crate::TimeoutLayer). Layers forward the timeout of the handler they wrap.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Turns a closure into a [`Handler`]:
+///
+/// ```no_run
+/// # use pg_queue::{handler_fn, ProcessFlow, Queue};
+/// # #[derive(serde::Serialize, serde::Deserialize)]
+/// # struct Email {
+/// #     address: String,
+/// # }
+/// # async fn example(pool: sqlx::PgPool, queue: Queue<Email>) -> Result<(), pg_queue::Error> {
+/// queue.process(&pool, handler_fn(|tx, item: Email| Box::pin(async move {
+///     sqlx::query("INSERT INTO emails (address) VALUES ($1)")
+///         .bind(item.address)
+///         .execute(&mut **tx)
+///         .await
+///         .map_err(|_| ())?;
+///     Ok(ProcessFlow::Success)
+/// })))
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn handler_fn<T, F>(f: F) -> HandlerFn<F>
+where
+    F: for<'a, 'c> FnOnce(
+        &'a mut Transaction<'c, Postgres>,
+        T,
+    ) -> BoxFuture<'a, Result<ProcessFlow, ()>>,
+{
+    HandlerFn(f)
+}
+
+/// A [`Handler`] created by [`handler_fn`].
+#[derive(Debug, Clone, Copy)]
+pub struct HandlerFn<F>(F);
+
+impl<T, F> Handler<T> for HandlerFn<F>
+where
+    F: for<'a, 'c> FnOnce(
+        &'a mut Transaction<'c, Postgres>,
+        T,
+    ) -> BoxFuture<'a, Result<ProcessFlow, ()>>,
+{
+    fn handle<'a>(
+        self,
+        tx: &'a mut Transaction<'_, Postgres>,
+        item: T,
+    ) -> BoxFuture<'a, Result<ProcessFlow, ()>> {
+        (self.0)(tx, item)
+    }
+}
+
+/// Processes the items dequeued together by [`Queue::process_batch`](crate::Queue::process_batch).
+///
+/// The handler receives the transaction holding the locks on all items, and returns a [`ProcessFlow`] for every
+/// item, in the order of `items`.
+pub trait BatchHandler<T> {
+    fn handle<'a>(
+        self,
+        tx: &'a mut Transaction<'_, Postgres>,
+        items: &'a [T],
+    ) -> BoxFuture<'a, Result<Vec<ProcessFlow>, ()>>;
+}
+
+/// Turns a closure into a [`BatchHandler`]:
+///
+/// ```no_run
+/// # use pg_queue::{batch_handler_fn, ProcessFlow, Queue};
+/// # #[derive(serde::Serialize, serde::Deserialize)]
+/// # struct Email {
+/// #     address: String,
+/// # }
+/// # async fn example(pool: sqlx::PgPool, queue: Queue<Email>) -> Result<(), pg_queue::Error> {
+/// queue.process_batch(&pool, 100, batch_handler_fn(|tx, items: &[Email]| Box::pin(async move {
+///     let addresses: Vec<_> = items.iter().map(|item| item.address.clone()).collect();
+///     sqlx::query("INSERT INTO emails (address) SELECT * FROM unnest($1::text[])")
+///         .bind(addresses)
+///         .execute(&mut **tx)
+///         .await
+///         .map_err(|_| ())?;
+///     Ok(vec![ProcessFlow::Success; items.len()])
+/// })))
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn batch_handler_fn<T, F>(f: F) -> BatchHandlerFn<F>
+where
+    F: for<'a, 'c> FnOnce(
+        &'a mut Transaction<'c, Postgres>,
+        &'a [T],
+    ) -> BoxFuture<'a, Result<Vec<ProcessFlow>, ()>>,
+{
+    BatchHandlerFn(f)
+}
+
+/// A [`BatchHandler`] created by [`batch_handler_fn`].
+#[derive(Debug, Clone, Copy)]
+pub struct BatchHandlerFn<F>(F);
+
+impl<T, F> BatchHandler<T> for BatchHandlerFn<F>
+where
+    F: for<'a, 'c> FnOnce(
+        &'a mut Transaction<'c, Postgres>,
+        &'a [T],
+    ) -> BoxFuture<'a, Result<Vec<ProcessFlow>, ()>>,
+{
+    fn handle<'a>(
+        self,
+        tx: &'a mut Transaction<'_, Postgres>,
+        items: &'a [T],
+    ) -> BoxFuture<'a, Result<Vec<ProcessFlow>, ()>> {
+        (self.0)(tx, items)
+    }
+}
+
+/// Decodes dequeued items, given their kind. Fails with the message the item is marked as failed with if it cannot be
+/// decoded.
+pub(crate) trait Dispatch {
+    type Decoded: Decoded;
+
+    fn decode(self, kind: Option<&str>, item: Value) -> Result<Self::Decoded, String>;
+}
+
+/// A decoded item, ready to be handled inside the processing transaction.
+pub(crate) trait Decoded {
+    /// Overrides the queue's retry policy for this item.
+    fn retry(&self) -> Option<RetryPolicy> {
+        None
+    }
+
+    /// Overrides the queue's timeout for this item, unless the item has its own.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    fn handle<'a>(
+        self,
+        tx: &'a mut Transaction<'_, Postgres>,
+    ) -> BoxFuture<'a, Result<ProcessFlow, ()>>;
+}
+
+/// Dispatches every item to a [`Handler`] of `T`, regardless of its kind.
+pub(crate) struct Typed<H, T>(H, PhantomData<fn() -> T>);
+
+impl<H, T> Typed<H, T> {
+    pub(crate) fn new(handler: H) -> Self {
+        Self(handler, PhantomData)
+    }
+}
+
+impl<H: Clone, T> Clone for Typed<H, T> {
+    fn clone(&self) -> Self {
+        Self::new(self.0.clone())
+    }
+}
+
+impl<H: Handler<T>, T: DeserializeOwned> Dispatch for Typed<H, T> {
+    type Decoded = Ready<H, T>;
+
+    fn decode(self, _kind: Option<&str>, item: Value) -> Result<Ready<H, T>, String> {
+        let item = serde_json::from_value(item)
+            .map_err(|error| format!("unable to deserialize item: {error}"))?;
+        Ok(Ready(self.0, item))
+    }
+}
+
+/// An item decoded by [`Typed`].
+pub(crate) struct Ready<H, T>(H, T);
+
+impl<H: Handler<T>, T> Decoded for Ready<H, T> {
+    fn timeout(&self) -> Option<Duration> {
+        self.0.timeout()
+    }
+
+    fn handle<'a>(
+        self,
+        tx: &'a mut Transaction<'_, Postgres>,
+    ) -> BoxFuture<'a, Result<ProcessFlow, ()>> {
+        self.0.handle(tx, self.1)
+    }
+}
+
+/// The message of a panic caught while handling an item, if it has one.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload
+            .downcast_ref::<String>()
+            .map_or("no message", String::as_str),
+    }
+}
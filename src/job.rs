@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{Acquire, Postgres, Transaction};
+
+use crate::handler::{Decoded, Dispatch, Ready, Typed};
+use crate::{
+    EnqueueOptions, Error, Handler, Outcome, ProcessFlow, Queue, QueueConfig, RetryPolicy,
+};
+
+/// An item type identified by its kind, so that items of different types can share a queue. Enqueued with
+/// [`Queue::enqueue_job`], and routed to their handler by a [`Registry`].
+///
+/// The remaining constants are defaults for the items of the job, overriding those of the queue they are enqueued on.
+/// They can be declared with `#[derive(Job)]` (requires the `derive` feature):
+///
+/// ```no_run
+/// # #[cfg(feature = "derive")]
+/// # mod example {
+/// # use std::time::Duration;
+/// # use pg_queue::{Job, RetryPolicy};
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Serialize, Deserialize, Job)]
+/// #[job(kind = "send_email", queue = "emails", priority = 10, max_attempts = 3)]
+/// #[job(retry = RetryPolicy::Fixed(Duration::from_secs(60)), timeout = Duration::from_secs(30))]
+/// struct SendEmail {
+///     address: String,
+/// }
+/// # }
+/// ```
+pub trait Job: Serialize + DeserializeOwned {
+    /// Stored in the `kind` column of every enqueued item. Must be unique among the jobs sharing a queue, and should
+    /// not change while items of this kind are enqueued.
+    const KIND: &'static str;
+    /// The name of the queue created by [`Queue::job`].
+    const QUEUE: &'static str = "default";
+    const PRIORITY: Option<i32> = None;
+    const MAX_ATTEMPTS: Option<i32> = None;
+    /// Used when items are requeued by [`Queue::process_jobs`], and by every processing method of the queue created by
+    /// [`Queue::job`]. Other queues only know the kind of the job's items, not its type, so [`Queue::process`],
+    /// [`Queue::process_batch`] and [`Queue::nack`] retry them according to the queue's [`RetryPolicy`], even when they
+    /// were enqueued with [`Queue::enqueue_job`].
+    const RETRY: Option<RetryPolicy> = None;
+    /// Used for items without a timeout of their own, unless their handler has one (see
+    /// [`TimeoutLayer`](crate::TimeoutLayer)), by [`Queue::process_jobs`] and every processing method of the queue
+    /// created by [`Queue::job`]. See [`Queue::with_timeout`].
+    const TIMEOUT: Option<Duration> = None;
+}
+
+/// Handlers of [`Job`]s, by kind. Processes queues of mixed items with [`Queue::process_jobs`] or
+/// [`WorkerPool::with_registry`](crate::WorkerPool::with_registry).
+///
+/// ```no_run
+/// # use pg_queue::{handler_fn, Job, ProcessFlow, Queue, Registry};
+/// # #[derive(serde::Serialize, serde::Deserialize)]
+/// # struct SendEmail;
+/// # impl Job for SendEmail {
+/// #     const KIND: &'static str = "send_email";
+/// # }
+/// # #[derive(serde::Serialize, serde::Deserialize)]
+/// # struct Payout;
+/// # impl Job for Payout {
+/// #     const KIND: &'static str = "payout";
+/// # }
+/// # async fn example(pool: sqlx::PgPool) -> Result<(), pg_queue::Error> {
+/// # let send_email = handler_fn(|_tx, _job: SendEmail| Box::pin(async { Ok(ProcessFlow::Success) }));
+/// # let payout = handler_fn(|_tx, _job: Payout| Box::pin(async { Ok(ProcessFlow::Success) }));
+/// let registry = Registry::new()
+///     .register::<SendEmail, _>(send_email)
+///     .register::<Payout, _>(payout);
+/// let queue: Queue = Queue::new("jobs");
+/// queue.process_jobs(&pool, &registry).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct Registry {
+    handlers: HashMap<&'static str, Arc<dyn Registered>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handles items of kind `J::KIND` with `handler`, replacing any handler registered for that kind.
+    pub fn register<J, H>(mut self, handler: H) -> Self
+    where
+        J: Job + Send + 'static,
+        H: Handler<J> + Clone + Send + Sync + 'static,
+    {
+        self.handlers.insert(J::KIND, Arc::new(Typed::new(handler)));
+        self
+    }
+
+    /// The kinds with a registered handler.
+    pub fn kinds(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.handlers.keys().copied()
+    }
+}
+
+impl std::fmt::Debug for Registry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Registry")
+            .field("kinds", &self.handlers.keys())
+            .finish()
+    }
+}
+
+impl<J: Job> Queue<J> {
+    /// A queue named [`Job::QUEUE`], stored according to the default [`QueueConfig`], for the items of `J`.
+    pub fn job() -> Self {
+        Self::job_with_config(QueueConfig::default())
+    }
+
+    /// A queue named [`Job::QUEUE`] for the items of `J`, which are enqueued with their kind and the job's defaults.
+    pub fn job_with_config(config: QueueConfig) -> Self {
+        let mut queue = Self::with_config(J::QUEUE, config);
+        queue.kind = Some(J::KIND);
+        if let Some(priority) = J::PRIORITY {
+            queue = queue.with_priority(priority);
+        }
+        if let Some(max_attempts) = J::MAX_ATTEMPTS {
+            queue = queue.with_max_attempts(max_attempts);
+        }
+        if let Some(retry) = J::RETRY {
+            queue = queue.with_retry(retry);
+        }
+        if let Some(timeout) = J::TIMEOUT {
+            queue = queue.with_timeout(timeout);
+        }
+        queue
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Queue<T> {
+    /// Enqueues a job of any type on this queue, tagged with its kind so that [`Queue::process_jobs`] can route it.
+    /// The job's priority and max attempts take precedence over the queue's. Its retry policy and timeout are not
+    /// stored with the item, and apply when it is processed by [`Queue::process_jobs`].
+    pub async fn enqueue_job<'a, A, J>(&self, conn: A, job: J) -> Result<i64, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+        J: Job,
+    {
+        let mut conn = conn.acquire().await?;
+        self.insert(
+            &mut conn,
+            serde_json::to_value(job)?,
+            Some(J::KIND),
+            EnqueueOptions {
+                priority: J::PRIORITY,
+                max_attempts: J::MAX_ATTEMPTS,
+                ..EnqueueOptions::default()
+            },
+        )
+        .await
+    }
+
+    /// Processes the next item like [`Queue::process`], with the handler registered in `registry` for the item's kind.
+    /// Items without a kind, or of a kind without a handler, are marked as failed and returned as
+    /// [`Outcome::Malformed`].
+    pub async fn process_jobs<'a, A>(
+        &self,
+        conn: A,
+        registry: &Registry,
+    ) -> Result<Option<Outcome>, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        self.process_with(conn, registry).await
+    }
+}
+
+impl Dispatch for &Registry {
+    type Decoded = Box<dyn Erased>;
+
+    fn decode(self, kind: Option<&str>, item: Value) -> Result<Box<dyn Erased>, String> {
+        let kind = kind.ok_or("item has no kind, it was not enqueued as a job")?;
+        let handler = self
+            .handlers
+            .get(kind)
+            .ok_or_else(|| format!("no handler registered for kind `{kind}`"))?;
+        handler.decode(item)
+    }
+}
+
+/// A registered handler, with its job type erased.
+trait Registered: Send + Sync {
+    fn decode(&self, item: Value) -> Result<Box<dyn Erased>, String>;
+}
+
+impl<H, J> Registered for Typed<H, J>
+where
+    J: Job + Send + 'static,
+    H: Handler<J> + Clone + Send + Sync + 'static,
+{
+    fn decode(&self, item: Value) -> Result<Box<dyn Erased>, String> {
+        let ready = Dispatch::decode(self.clone(), Some(J::KIND), item)?;
+        Ok(Box::new(ready))
+    }
+}
+
+/// A decoded job, with its type erased.
+pub(crate) trait Erased: Send {
+    fn retry(&self) -> Option<RetryPolicy>;
+
+    fn timeout(&self) -> Option<Duration>;
+
+    fn handle_boxed<'a>(
+        self: Box<Self>,
+        tx: &'a mut Transaction<'_, Postgres>,
+    ) -> BoxFuture<'a, Result<ProcessFlow, ()>>;
+}
+
+impl<H: Handler<J> + Send, J: Job + Send> Erased for Ready<H, J> {
+    fn retry(&self) -> Option<RetryPolicy> {
+        J::RETRY
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        Decoded::timeout(self).or(J::TIMEOUT)
+    }
+
+    fn handle_boxed<'a>(
+        self: Box<Self>,
+        tx: &'a mut Transaction<'_, Postgres>,
+    ) -> BoxFuture<'a, Result<ProcessFlow, ()>> {
+        (*self).handle(tx)
+    }
+}
+
+impl Decoded for Box<dyn Erased> {
+    fn retry(&self) -> Option<RetryPolicy> {
+        Erased::retry(&**self)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        Erased::timeout(&**self)
+    }
+
+    fn handle<'a>(
+        self,
+        tx: &'a mut Transaction<'_, Postgres>,
+    ) -> BoxFuture<'a, Result<ProcessFlow, ()>> {
+        self.handle_boxed(tx)
+    }
+}
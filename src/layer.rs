@@ -0,0 +1,277 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use sqlx::{Postgres, Transaction};
+
+use crate::{Handler, ProcessFlow};
+
+/// Wraps a [`Handler`] into another one, to share concerns like timing or timeouts between handlers. Mirrors
+/// `tower::Layer`:
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use pg_queue::{handler_fn, Layer, MetricsLayer, ProcessFlow, Queue, TimeoutLayer};
+/// # fn record(_result: &Result<ProcessFlow, ()>, _elapsed: Duration) {}
+/// # async fn example(pool: sqlx::PgPool, queue: Queue<u64>) -> Result<(), pg_queue::Error> {
+/// # let handler = handler_fn(|_tx, _item: u64| Box::pin(async { Ok(ProcessFlow::Success) }));
+/// let handler = TimeoutLayer::new(Duration::from_secs(30)).layer(handler);
+/// let handler = MetricsLayer::new(|result, elapsed| record(result, elapsed)).layer(handler);
+/// queue.process(&pool, handler).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Layers apply to the handlers registered in a [`Registry`](crate::Registry) as well, one handler at a time.
+pub trait Layer<H> {
+    type Handler;
+
+    fn layer(&self, inner: H) -> Self::Handler;
+}
+
+/// Abandons attempts whose handler runs for longer than a timeout, overriding the timeout of the items' [`Job`] and
+/// queue. The timeout is enforced by the queue like [`Queue::with_timeout`]: the item is requeued with a message, and
+/// the writes made by the handler are rolled back.
+///
+/// [`Job`]: crate::Job
+/// [`Queue::with_timeout`]: crate::Queue::with_timeout
+#[derive(Debug, Clone, Copy)]
+pub struct TimeoutLayer {
+    timeout: Duration,
+}
+
+impl TimeoutLayer {
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+impl<H> Layer<H> for TimeoutLayer {
+    type Handler = Timeout<H>;
+
+    fn layer(&self, inner: H) -> Timeout<H> {
+        Timeout {
+            inner,
+            timeout: self.timeout,
+        }
+    }
+}
+
+/// A [`Handler`] created by [`TimeoutLayer`].
+#[derive(Debug, Clone, Copy)]
+pub struct Timeout<H> {
+    inner: H,
+    timeout: Duration,
+}
+
+impl<T, H> Handler<T> for Timeout<H>
+where
+    H: Handler<T>,
+{
+    fn handle<'a>(
+        self,
+        tx: &'a mut Transaction<'_, Postgres>,
+        item: T,
+    ) -> BoxFuture<'a, Result<ProcessFlow, ()>> {
+        self.inner.handle(tx, item)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        Some(self.timeout)
+    }
+}
+
+/// Reports the result of every call to a handler, along with how long it took, e.g. to record metrics:
+///
+/// ```no_run
+/// # use pg_queue::MetricsLayer;
+/// # struct Histogram;
+/// # impl Histogram {
+/// #     fn record(&self, _value: f64) {}
+/// # }
+/// # struct Counter;
+/// # impl Counter {
+/// #     fn increment(&self, _value: u64) {}
+/// # }
+/// # let (histogram, errors) = (Histogram, Counter);
+/// MetricsLayer::new(move |result, elapsed| {
+///     histogram.record(elapsed.as_secs_f64());
+///     if result.is_err() {
+///         errors.increment(1);
+///     }
+/// })
+/// # ;
+/// ```
+///
+/// Attempts abandoned before the handler returns, because it timed out or panicked, are reported as errors.
+#[derive(Clone)]
+pub struct MetricsLayer {
+    record: Record,
+}
+
+type Record = Arc<dyn Fn(&Result<ProcessFlow, ()>, Duration) + Send + Sync>;
+
+impl MetricsLayer {
+    pub fn new(
+        record: impl Fn(&Result<ProcessFlow, ()>, Duration) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            record: Arc::new(record),
+        }
+    }
+}
+
+impl<H> Layer<H> for MetricsLayer {
+    type Handler = Metrics<H>;
+
+    fn layer(&self, inner: H) -> Metrics<H> {
+        Metrics {
+            inner,
+            record: self.record.clone(),
+        }
+    }
+}
+
+/// A [`Handler`] created by [`MetricsLayer`].
+#[derive(Clone)]
+pub struct Metrics<H> {
+    inner: H,
+    record: Record,
+}
+
+impl<T, H> Handler<T> for Metrics<H>
+where
+    H: Handler<T>,
+{
+    fn handle<'a>(
+        self,
+        tx: &'a mut Transaction<'_, Postgres>,
+        item: T,
+    ) -> BoxFuture<'a, Result<ProcessFlow, ()>> {
+        let mut pending = Pending {
+            record: Some(self.record),
+            started: Instant::now(),
+        };
+        let handled = self.inner.handle(tx, item);
+        Box::pin(async move {
+            let result = handled.await;
+            pending.finish(&result);
+            result
+        })
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.inner.timeout()
+    }
+}
+
+/// A call to a handler wrapped by [`Metrics`], reported as an error if it is dropped before it finishes.
+struct Pending {
+    record: Option<Record>,
+    started: Instant,
+}
+
+impl Pending {
+    fn finish(&mut self, result: &Result<ProcessFlow, ()>) {
+        if let Some(record) = self.record.take() {
+            record(result, self.started.elapsed());
+        }
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.finish(&Err(()));
+    }
+}
+
+/// Runs handlers inside a `pg_queue.handle` tracing span, recording the item type and the result of the handler.
+/// Attempts abandoned before the handler returns, because it timed out or panicked, are logged as such.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TraceLayer;
+
+#[cfg(feature = "tracing")]
+impl TraceLayer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<H> Layer<H> for TraceLayer {
+    type Handler = Trace<H>;
+
+    fn layer(&self, inner: H) -> Trace<H> {
+        Trace { inner }
+    }
+}
+
+/// A [`Handler`] created by [`TraceLayer`].
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy)]
+pub struct Trace<H> {
+    inner: H,
+}
+
+#[cfg(feature = "tracing")]
+impl<T, H> Handler<T> for Trace<H>
+where
+    H: Handler<T>,
+{
+    fn handle<'a>(
+        self,
+        tx: &'a mut Transaction<'_, Postgres>,
+        item: T,
+    ) -> BoxFuture<'a, Result<ProcessFlow, ()>> {
+        use tracing::Instrument;
+
+        let span = tracing::info_span!(
+            "pg_queue.handle",
+            item = std::any::type_name::<T>(),
+            flow = tracing::field::Empty,
+        );
+        let mut pending = Traced {
+            span: Some(span.clone()),
+            started: Instant::now(),
+        };
+        let handled = span.in_scope(|| self.inner.handle(tx, item));
+        Box::pin(
+            async move {
+                let result = handled.await;
+                pending.span = None;
+                let elapsed = pending.started.elapsed();
+                match &result {
+                    Ok(flow) => {
+                        tracing::Span::current().record("flow", tracing::field::debug(flow));
+                        tracing::debug!(?elapsed, "item handled");
+                    }
+                    Err(()) => tracing::warn!(?elapsed, "handler failed"),
+                }
+                result
+            }
+            .instrument(span),
+        )
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.inner.timeout()
+    }
+}
+
+/// A call to a handler wrapped by [`Trace`], logged as abandoned if it is dropped before it finishes.
+#[cfg(feature = "tracing")]
+struct Traced {
+    span: Option<tracing::Span>,
+    started: Instant,
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for Traced {
+    fn drop(&mut self) {
+        if let Some(span) = self.span.take() {
+            let elapsed = self.started.elapsed();
+            span.in_scope(|| tracing::warn!(?elapsed, "handler abandoned"));
+        }
+    }
+}
@@ -1,90 +1,1288 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::panic::AssertUnwindSafe;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::Value;
-use sqlx::error::BoxDynError;
-use sqlx::migrate::Migrator;
-use sqlx::query;
-use sqlx::{Acquire, Postgres};
+use sqlx::postgres::types::PgInterval;
+use sqlx::query_as;
+use sqlx::{Acquire, PgConnection, Postgres, Transaction};
+
+mod config;
+mod error;
+mod handler;
+mod job;
+mod layer;
+mod listen;
+mod migrate;
+mod reaper;
+mod retry;
+mod schedule;
+mod worker;
 
-static MIGRATOR: Migrator = sqlx::migrate!(); // defaults to "./migrations"
+pub use config::QueueConfig;
+pub use error::Error;
+pub use handler::{batch_handler_fn, handler_fn, BatchHandler, BatchHandlerFn, Handler, HandlerFn};
+use handler::{panic_message, Decoded, Dispatch, Typed};
+pub use job::{Job, Registry};
+pub use layer::{Layer, Metrics, MetricsLayer, Timeout, TimeoutLayer};
+#[cfg(feature = "tracing")]
+pub use layer::{Trace, TraceLayer};
+pub use listen::Listener;
+pub use migrate::{migrate, pending_migrations, MIGRATOR};
+#[cfg(feature = "derive")]
+pub use pg_queue_derive::Job;
+pub use reaper::Reaper;
+pub use retry::RetryPolicy;
+pub use schedule::{Misfire, Schedule, Scheduler};
+pub use tokio_util::sync::CancellationToken;
+pub use worker::{WorkerPool, WorkerState, WorkerStatus};
 
-/// A fifo queue backed by a postgres table. Not suitable for high-throughput, but enough for ~1k items/sec.
+/// A priority queue backed by a postgres table. Not suitable for high-throughput, but enough for ~1k items/sec.
+///
+/// Items are stored as JSONB, and (de)serialized to `T` when enqueueing and processing. Many queues can share the
+/// same table, each only seeing the rows carrying its name. Items are dequeued by descending priority, then in the
+/// order they are due.
+///
+/// The queue assumes the following database schema, created by [`migrate`]:
+///
+/// ```text
+/// id BIGSERIAL PRIMARY KEY
+/// queue TEXT
+/// status 'ready' | 'in-progress' | 'failed' | 'completed'
+/// item JSONB
+/// message TEXT
+/// completed_at TIMESTAMPTZ
+/// locked_until TIMESTAMPTZ
+/// attempts INT
+/// max_attempts INT
+/// scheduled_at TIMESTAMPTZ
+/// run_after TIMESTAMPTZ
+/// priority INT
+/// kind TEXT
+/// timeout INTERVAL
+/// ```
 ///
-/// The queue assumes the following database schema:
-///     
-///     id SERIAL AUTO INCREMENT
-///     status 0..2
-///     item JSONB
-///     error TEXT
-pub struct Queue {}
-
-impl Queue {
-    /// Enqueues a new item for processing. The item's processing status is set to 0, indicating that it is ready
-    /// for processing.
-    pub async fn enqueue<'a, A, T: Serialize>(conn: A, item: T) -> Result<u64, BoxDynError>
+/// Queues of [`Job`]s of several kinds, processed with a [`Registry`], have no item type of their own: they keep the
+/// default `T`, e.g. `let queue: Queue = Queue::new("jobs")`, and enqueue items with [`Queue::enqueue_job`].
+pub struct Queue<T = Value> {
+    name: String,
+    config: QueueConfig,
+    completion: CompletionPolicy,
+    max_attempts: i32,
+    retry: RetryPolicy,
+    priority: i32,
+    aging: Option<Duration>,
+    timeout: Option<Duration>,
+    kind: Option<&'static str>,
+    item: PhantomData<fn() -> T>,
+}
+
+/// How long an item claimed by [`Queue::process`] is protected from other workers before the processing transaction
+/// locks it. Once locked, the item is skipped by other workers regardless of its lease, and if the worker dies before
+/// locking it, the item can be claimed again after this delay.
+const PROCESS_LEASE: Duration = Duration::from_secs(30);
+
+/// The number of items above which [`Queue::enqueue_many`] switches from a multi-row insert to `COPY`.
+pub const COPY_THRESHOLD: usize = 10_000;
+
+impl<T> Queue<T> {
+    /// A queue stored according to the default [`QueueConfig`].
+    pub fn new(name: impl Into<String>) -> Self {
+        Self::with_config(name, QueueConfig::default())
+    }
+
+    pub fn with_config(name: impl Into<String>, config: QueueConfig) -> Self {
+        Self {
+            name: name.into(),
+            config,
+            completion: CompletionPolicy::default(),
+            max_attempts: 5,
+            retry: RetryPolicy::default(),
+            priority: 0,
+            aging: None,
+            timeout: None,
+            kind: None,
+            item: PhantomData,
+        }
+    }
+
+    /// Sets what happens to successfully processed items. Defaults to [`CompletionPolicy::Complete`].
+    pub fn with_completion(mut self, completion: CompletionPolicy) -> Self {
+        self.completion = completion;
+        self
+    }
+
+    /// Sets how many times newly enqueued items are dequeued before they are marked as failed. Defaults to 5.
+    pub fn with_max_attempts(mut self, max_attempts: i32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Sets how long requeued items wait before they are retried. Defaults to [`RetryPolicy::default`].
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Sets the priority of newly enqueued items, unless overridden by [`EnqueueOptions::priority`]. Items with a
+    /// higher priority are dequeued first. Defaults to 0.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Raises the priority of waiting items by one for every `aging` they have been due, so that low-priority items
+    /// are eventually dequeued even when higher-priority items keep coming in. Disabled by default.
+    ///
+    /// Dequeueing with aging cannot use the queue's index to find the next item, so it scans all ready items.
+    pub fn with_aging(mut self, aging: Duration) -> Self {
+        self.aging = Some(aging);
+        self
+    }
+
+    /// Abandons attempts whose handler runs for longer than `timeout` in [`Queue::process`], unless the item has its
+    /// own timeout (see [`EnqueueOptions::timeout`]), the handler has one (see [`TimeoutLayer`]) or its [`Job`]
+    /// declares one. Disabled by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn config(&self) -> &QueueConfig {
+        &self.config
+    }
+
+    pub fn completion(&self) -> CompletionPolicy {
+        self.completion
+    }
+
+    pub fn max_attempts(&self) -> i32 {
+        self.max_attempts
+    }
+
+    pub fn retry(&self) -> RetryPolicy {
+        self.retry
+    }
+
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    pub fn aging(&self) -> Option<Duration> {
+        self.aging
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// The kind of the items enqueued by this queue, set for queues of a [`Job`] created with [`Queue::job`]. Such
+    /// queues only dequeue items of their kind, so that jobs sharing a queue name do not dequeue each other's items.
+    pub fn kind(&self) -> Option<&'static str> {
+        self.kind
+    }
+
+    /// Applies the crate's pending migrations for this queue's configuration. See [`migrate`].
+    pub async fn migrate<'a, A>(&self, conn: A) -> Result<(), Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        migrate(conn, &self.config).await
+    }
+}
+
+impl<T> Clone for Queue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            config: self.config.clone(),
+            completion: self.completion,
+            max_attempts: self.max_attempts,
+            retry: self.retry,
+            priority: self.priority,
+            aging: self.aging,
+            timeout: self.timeout,
+            kind: self.kind,
+            item: PhantomData,
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Queue<T> {
+    /// Enqueues a new item for processing. The item's processing status is set to 'ready', indicating that it is
+    /// ready for processing. It may be dequeued up to the queue's max attempts.
+    ///
+    /// The item is inserted with a single statement, visible to workers as soon as `conn` commits it. To enqueue an
+    /// item atomically with other writes, use [`Queue::enqueue_in_tx`].
+    pub async fn enqueue<'a, A>(&self, conn: A, item: T) -> Result<i64, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        self.enqueue_with(conn, item, EnqueueOptions::default())
+            .await
+    }
+
+    /// Enqueues a new item that will not be processed before `at`.
+    pub async fn enqueue_at<'a, A>(&self, conn: A, item: T, at: DateTime<Utc>) -> Result<i64, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        let options = EnqueueOptions {
+            at: Some(at),
+            ..EnqueueOptions::default()
+        };
+        self.enqueue_with(conn, item, options).await
+    }
+
+    /// Enqueues a new item that will not be processed before `delay` has passed, according to the database clock.
+    pub async fn enqueue_in<'a, A>(&self, conn: A, item: T, delay: Duration) -> Result<i64, Error>
     where
         A: Acquire<'a, Database = Postgres>,
     {
-        let item = serde_json::to_value(item)?;
+        let options = EnqueueOptions {
+            delay,
+            ..EnqueueOptions::default()
+        };
+        self.enqueue_with(conn, item, options).await
+    }
+
+    /// Enqueues a new item, overriding the queue's defaults with `options`.
+    pub async fn enqueue_with<'a, A>(
+        &self,
+        conn: A,
+        item: T,
+        options: EnqueueOptions,
+    ) -> Result<i64, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        let mut conn = conn.acquire().await?;
+        self.insert(&mut conn, serde_json::to_value(item)?, self.kind, options)
+            .await
+    }
+
+    /// Enqueues a new item as part of the caller's transaction, without committing it. The item becomes visible to
+    /// workers if and only if `tx` commits, so it can be enqueued atomically with the writes that call for it
+    /// (the transactional outbox pattern). If `tx` rolls back, the item is discarded.
+    pub async fn enqueue_in_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item: T,
+    ) -> Result<i64, Error> {
+        self.enqueue_in_tx_with(tx, item, EnqueueOptions::default())
+            .await
+    }
+
+    /// Enqueues a new item as part of the caller's transaction like [`Queue::enqueue_in_tx`], overriding the queue's
+    /// defaults with `options`.
+    pub async fn enqueue_in_tx_with(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item: T,
+        options: EnqueueOptions,
+    ) -> Result<i64, Error> {
+        self.insert(tx, serde_json::to_value(item)?, self.kind, options)
+            .await
+    }
+
+    /// Inserts an item with a single statement on `conn`, within whatever transaction `conn` is in.
+    async fn insert(
+        &self,
+        conn: &mut PgConnection,
+        item: Value,
+        kind: Option<&str>,
+        options: EnqueueOptions,
+    ) -> Result<i64, Error> {
+        let (id,): (i64,) = query_as(&self.config.render(
+            "
+            WITH inserted AS (
+              INSERT INTO {{queue}} (queue, kind, item, max_attempts, priority, timeout, scheduled_at, run_after)
+              SELECT $1, $8, $2, $3, $4, $9, due, due
+              FROM (SELECT coalesce($5, now()) + $6 AS due) scheduled
+              RETURNING id
+            )
+            SELECT id FROM inserted, pg_notify('pg_queue_' || md5($7), '')",
+        ))
+        .bind(&self.name)
+        .bind(item)
+        .bind(options.max_attempts.unwrap_or(self.max_attempts))
+        .bind(options.priority.unwrap_or(self.priority))
+        .bind(options.at)
+        .bind(interval(options.delay))
+        .bind(self.config.channel_key(&self.name))
+        .bind(kind)
+        .bind(options.timeout.map(interval))
+        .fetch_one(conn)
+        .await?;
+        Ok(id)
+    }
+
+    /// Enqueues all `items` in a single transaction, with the queue's defaults. Returns the ids of the items, in the
+    /// same order.
+    ///
+    /// Up to [`COPY_THRESHOLD`] items are inserted with a single multi-row insert. Larger batches are streamed with
+    /// `COPY`, after reserving their ids from the table's sequence.
+    pub async fn enqueue_many<'a, A>(
+        &self,
+        conn: A,
+        items: impl IntoIterator<Item = T>,
+    ) -> Result<Vec<i64>, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        self.enqueue_many_with(conn, items, EnqueueOptions::default())
+            .await
+    }
+
+    /// Enqueues all `items` like [`Queue::enqueue_many`], overriding the queue's defaults with `options` for every
+    /// item.
+    pub async fn enqueue_many_with<'a, A>(
+        &self,
+        conn: A,
+        items: impl IntoIterator<Item = T>,
+        options: EnqueueOptions,
+    ) -> Result<Vec<i64>, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        let items = items
+            .into_iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        if items.is_empty() {
+            return Ok(Vec::new());
+        }
+
         let mut tx = conn.begin().await?;
-        let id = query!("INSERT into queue VALUES (item) (item) RETURNING id")
-            .fetch_one(tx)
-            .await?;
+        let ids = if items.len() <= COPY_THRESHOLD {
+            let mut ids: Vec<i64> = query_as(&self.config.render(
+                "
+                INSERT INTO {{queue}} (queue, kind, item, max_attempts, priority, timeout, scheduled_at, run_after)
+                SELECT $1, $5, item, $2, $3, $8, due, due
+                FROM unnest($4::jsonb[]) WITH ORDINALITY AS items (item, position),
+                  (SELECT coalesce($6, now()) + $7 AS due) scheduled
+                ORDER BY position
+                RETURNING id",
+            ))
+            .bind(&self.name)
+            .bind(options.max_attempts.unwrap_or(self.max_attempts))
+            .bind(options.priority.unwrap_or(self.priority))
+            .bind(items)
+            .bind(self.kind)
+            .bind(options.at)
+            .bind(interval(options.delay))
+            .bind(options.timeout.map(interval))
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|(id,)| id)
+            .collect();
+            // Ids are drawn from the sequence in insertion order, which follows the order of the items.
+            ids.sort_unstable();
+            ids
+        } else {
+            self.copy(&mut tx, items, options).await?
+        };
+        listen::notify(&mut tx, &self.config, &self.name).await?;
         tx.commit().await?;
-        Ok(id)
+        Ok(ids)
     }
 
-    /// Processes the next value from the queue, calling `f` on the value. Dequeueing has the following properties:
-    /// - if `f` returns an error, the item is requeued.
+    /// Inserts `items` using `COPY`, which cannot return the ids it assigns, so they are taken from the sequence first.
+    async fn copy(
+        &self,
+        conn: &mut PgConnection,
+        items: Vec<Value>,
+        options: EnqueueOptions,
+    ) -> Result<Vec<i64>, Error> {
+        let mut ids: Vec<i64> = query_as(
+            "SELECT nextval(pg_get_serial_sequence($1, 'id')) FROM generate_series(1, $2)",
+        )
+        .bind(self.config.ident("queue"))
+        .bind(items.len() as i64)
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|(id,)| id)
+        .collect();
+        ids.sort_unstable();
+        let (due,): (DateTime<Utc>,) = query_as("SELECT coalesce($1, now()) + $2")
+            .bind(options.at)
+            .bind(interval(options.delay))
+            .fetch_one(&mut *conn)
+            .await?;
+
+        // Every row ends with the same columns, taken from `options` or the queue.
+        let due = due.to_rfc3339();
+        let timeout = match options.timeout {
+            Some(timeout) => format!("{} microseconds", interval(timeout).microseconds),
+            None => "\\N".to_owned(),
+        };
+        let tail = format!(
+            "\t{}\t{}\t{timeout}\t{due}\t{due}\n",
+            options.max_attempts.unwrap_or(self.max_attempts),
+            options.priority.unwrap_or(self.priority),
+        );
+
+        let mut copy = conn
+            .copy_in_raw(&self.config.render(
+                "
+                COPY {{queue}} (id, queue, kind, item, max_attempts, priority, timeout, scheduled_at, run_after)
+                FROM STDIN",
+            ))
+            .await?;
+        let mut rows = String::new();
+        for (id, item) in ids.iter().zip(items) {
+            rows.push_str(&format!("{id}\t"));
+            copy_text(&self.name, &mut rows);
+            rows.push('\t');
+            match self.kind {
+                Some(kind) => copy_text(kind, &mut rows),
+                None => rows.push_str("\\N"),
+            }
+            rows.push('\t');
+            copy_text(&item.to_string(), &mut rows);
+            rows.push_str(&tail);
+            if rows.len() >= 1 << 20 {
+                copy.send(std::mem::take(&mut rows).into_bytes()).await?;
+            }
+        }
+        copy.send(rows.into_bytes()).await?;
+        copy.finish().await?;
+        Ok(ids)
+    }
+
+    /// Processes the next value from the queue, awaiting `f` on the value. Returns Ok(None) without calling `f` if
+    /// the queue is empty. Dequeueing has the following properties:
+    /// - if `f` returns an error, the item is requeued and process returns [`Error::Handler`].
+    /// - if `f` panics, the panic is caught and the item is requeued like on an error, with the panic message stored
+    ///   as the item's message.
+    /// - if `f` runs for longer than the item's timeout, it is dropped and the item is requeued like on a panic. The
+    ///   timeout is taken from [`EnqueueOptions::timeout`], a [`TimeoutLayer`] wrapping `f`, the item's [`Job`] or
+    ///   [`Queue::with_timeout`], in that order, and there is none by default. It also applies to every statement `f`
+    ///   runs in the processing transaction, as its `statement_timeout`, so that a handler stuck in the database, e.g.
+    ///   waiting for a lock, releases the transaction.
+    /// - requeued items are retried after a delay determined by the queue's [`RetryPolicy`].
     /// - if `f` returns Ok(ProcessFlow::Fail), the item is permanently marked as failed.
-    /// - if `f` returns Ok(ProcessFlow::Continue), the item is requeued, but process returns with Ok(()).
-    /// - if `f` returns Ok(ProcessFlow::Success), the item is marked as processed.
+    /// - if `f` returns Ok(ProcessFlow::Requeue), the item is requeued, but process returns with
+    ///   Ok(Some(Outcome::Requeue)).
+    /// - if `f` returns Ok(ProcessFlow::Success), the item is completed according to the queue's [`CompletionPolicy`].
+    /// - if the item cannot be deserialized into `T`, `f` is not called and the item is permanently marked as failed,
+    ///   with the deserialization error stored as the message. Process returns Ok(Some(Outcome::Malformed)).
     ///
     /// Database atomicity is used to ensure that the queue is always in a consistent state, meaning that an item
-    /// process will always be retried until it reaches ProcessFlow::Fail or ProcessFlow::Success. `f` is responsible for
-    /// storing metadata in the job to determine if retrying should fail permanently.
+    /// process will always be retried until it reaches ProcessFlow::Fail or ProcessFlow::Success, or runs out of
+    /// attempts. Every dequeue counts as an attempt, which is committed before `f` is called. An item that is requeued
+    /// on its last attempt, whether by an error, a panic, a timeout or ProcessFlow::Requeue, is marked as failed
+    /// instead, and process returns Ok(Some(Outcome::Fail)) with the stored message. An item whose worker crashed on
+    /// its last attempt is marked as failed the next time it is dequeued (or reaped by a [`Reaper`]).
+    ///
+    /// `f` runs inside the processing transaction, which holds the lock on the item for as long as `f` runs, so other
+    /// workers skip it. Writes made by `f` through the transaction are committed on ProcessFlow::Success and
+    /// ProcessFlow::Fail, and rolled back when the item is requeued.
     pub async fn process<'a, A>(
+        &self,
+        conn: A,
+        f: impl Handler<T>,
+    ) -> Result<Option<Outcome>, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        self.process_with(conn, Typed::new(f)).await
+    }
+
+    /// Processes the next item like [`Queue::process`], decoding it and handling it with `f`.
+    async fn process_with<'a, A>(&self, conn: A, f: impl Dispatch) -> Result<Option<Outcome>, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        let mut conn = conn.acquire().await?;
+
+        let Some(claimed) = self.claim(&mut conn, PROCESS_LEASE, 1).await?.pop() else {
+            return Ok(None);
+        };
+        let (id, attempts, timeout) = (claimed.id, claimed.attempts, claimed.timeout());
+        if let Some(message) = claimed.exhausted() {
+            self.set_failed(&mut conn, id, attempts, &message).await?;
+            return Ok(Some(Outcome::Fail(message)));
+        }
+
+        let mut tx = conn.begin().await?;
+        sqlx::query(
+            &self
+                .config
+                .render("SELECT FROM {{queue}} WHERE id = $1 FOR UPDATE"),
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+        let item = match f.decode(claimed.kind.as_deref(), claimed.item) {
+            Ok(item) => item,
+            Err(message) => {
+                self.set_failed(&mut tx, id, attempts, &message).await?;
+                tx.commit().await?;
+                return Ok(Some(Outcome::Malformed(message)));
+            }
+        };
+
+        let delay = item.retry().unwrap_or(self.retry).delay(attempts);
+        let timeout = timeout.or(item.timeout()).or(self.timeout);
+        let statement_timeout = match timeout {
+            Some(timeout) => Some(limit_statements(&mut tx, timeout).await?),
+            None => None,
+        };
+        let handle = AssertUnwindSafe(async { item.handle(&mut tx).await }).catch_unwind();
+        let handled = handle_within(timeout, handle).await;
+        let handled = match handled {
+            Some(Ok(result)) => result,
+            abandoned => {
+                // The handler's future has been dropped, so its writes are rolled back like on an error.
+                tx.rollback().await?;
+                let message = match abandoned {
+                    Some(Err(payload)) => format!("handler panicked: {}", panic_message(&*payload)),
+                    _ => format!("handler timed out after {:?}", timeout.unwrap_or_default()),
+                };
+                return match self
+                    .requeue(&mut conn, id, attempts, delay, Some(&message))
+                    .await?
+                    .flatten()
+                {
+                    None => Err(Error::Handler(format!("{message}, item requeued"))),
+                    Some(message) => Ok(Some(Outcome::Fail(message))),
+                };
+            }
+        };
+        if let (Ok(ProcessFlow::Success | ProcessFlow::Fail(_)), Some(previous)) =
+            (&handled, &statement_timeout)
+        {
+            restore_statements(&mut tx, previous).await?;
+        }
+        let outcome = match handled {
+            Ok(ProcessFlow::Fail(error)) => {
+                self.set_failed(&mut tx, id, attempts, &error).await?;
+                tx.commit().await?;
+                Outcome::Fail(error)
+            }
+            Ok(ProcessFlow::Success) => {
+                self.set_completed(&mut tx, id, attempts).await?;
+                tx.commit().await?;
+                Outcome::Success
+            }
+            Ok(ProcessFlow::Requeue) => {
+                tx.rollback().await?;
+                match self
+                    .requeue(&mut conn, id, attempts, delay, None)
+                    .await?
+                    .flatten()
+                {
+                    None => Outcome::Requeue,
+                    Some(message) => Outcome::Fail(message),
+                }
+            }
+            Err(()) => {
+                tx.rollback().await?;
+                match self
+                    .requeue(&mut conn, id, attempts, delay, None)
+                    .await?
+                    .flatten()
+                {
+                    None => return Err(Error::Handler("item requeued".to_owned())),
+                    Some(message) => Outcome::Fail(message),
+                }
+            }
+        };
+        Ok(Some(outcome))
+    }
+
+    /// Processes up to `n` values from the queue in a single transaction, awaiting `f` on all of them at once. Returns
+    /// the outcome of every dequeued item, in the order they were dequeued, which is empty if the queue is empty.
+    ///
+    /// Items are claimed and locked like in [`Queue::process`]. Items that ran out of attempts or cannot be
+    /// deserialized are marked as failed and not passed to `f`. `f` returns a [`ProcessFlow`] for every item it
+    /// received, and all flows are applied in a single statement, committed together with the writes made by `f`.
+    /// Unlike [`Queue::process`], writes are committed even if some items are requeued.
+    ///
+    /// If `f` returns an error, panics, or does not return exactly one flow per item, its writes are rolled back, every
+    /// item is requeued and the error is returned.
+    ///
+    /// `f` times out like in [`Queue::process`], after the longest timeout of the items, taken from
+    /// [`EnqueueOptions::timeout`] or [`Queue::with_timeout`]. Timeouts declared by a [`Job`] only apply through
+    /// [`Queue::job`].
+    pub async fn process_batch<'a, A>(
+        &self,
         conn: A,
-        f: impl FnOnce(Value) -> Result<ProcessFlow<Value>, ()>,
-    ) -> Result<(), BoxDynError>
+        n: usize,
+        f: impl BatchHandler<T>,
+    ) -> Result<Vec<Outcome>, Error>
     where
         A: Acquire<'a, Database = Postgres>,
     {
+        let mut conn = conn.acquire().await?;
+
+        let claimed = self
+            .claim(&mut conn, PROCESS_LEASE, n.try_into().unwrap_or(i64::MAX))
+            .await?;
+        let mut outcomes = Vec::with_capacity(claimed.len());
+        let mut ids = Vec::with_capacity(claimed.len());
+        let mut items = Vec::with_capacity(claimed.len());
+        let mut timeouts = Vec::with_capacity(claimed.len());
+        for claimed in claimed {
+            if let Some(message) = claimed.exhausted() {
+                self.set_failed(&mut conn, claimed.id, claimed.attempts, &message)
+                    .await?;
+                outcomes.push(Some(Outcome::Fail(message)));
+                continue;
+            }
+            let timeout = claimed.timeout();
+            match serde_json::from_value(claimed.item) {
+                Ok(item) => {
+                    outcomes.push(None);
+                    ids.push((claimed.id, claimed.attempts));
+                    items.push(item);
+                    timeouts.push(timeout.or(self.timeout));
+                }
+                Err(error) => {
+                    let message = format!("unable to deserialize item: {error}");
+                    self.set_failed(&mut conn, claimed.id, claimed.attempts, &message)
+                        .await?;
+                    outcomes.push(Some(Outcome::Malformed(message)));
+                }
+            }
+        }
+        if items.is_empty() {
+            return Ok(outcomes.into_iter().flatten().collect());
+        }
+
         let mut tx = conn.begin().await?;
+        sqlx::query(
+            &self
+                .config
+                .render("SELECT FROM {{queue}} WHERE id = ANY($1) FOR UPDATE"),
+        )
+        .bind(ids.iter().map(|(id, _)| *id).collect::<Vec<_>>())
+        .execute(&mut *tx)
+        .await?;
+
+        // The batch may run for as long as its longest timeout, and indefinitely if any item has none.
+        let timeout = timeouts
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .and_then(|timeouts| timeouts.into_iter().max());
+        let statement_timeout = match timeout {
+            Some(timeout) => Some(limit_statements(&mut tx, timeout).await?),
+            None => None,
+        };
+        let handle = AssertUnwindSafe(async { f.handle(&mut tx, &items).await }).catch_unwind();
+        let handled = handle_within(timeout, handle).await;
+        let flows = match handled {
+            Some(Ok(Ok(flows))) if flows.len() == items.len() => flows,
+            handled => {
+                tx.rollback().await?;
+                let error = match handled {
+                    Some(Ok(Ok(flows))) => Some(format!(
+                        "handler returned {} flows for {} items",
+                        flows.len(),
+                        items.len()
+                    )),
+                    Some(Ok(Err(()))) => None,
+                    Some(Err(payload)) => {
+                        Some(format!("handler panicked: {}", panic_message(&*payload)))
+                    }
+                    None => Some(format!(
+                        "handler timed out after {:?}",
+                        timeout.unwrap_or_default()
+                    )),
+                };
+                let requeue = vec![ProcessFlow::Requeue; ids.len()];
+                self.settle(&mut conn, &ids, &requeue, error.as_deref())
+                    .await?;
+                return Err(Error::Handler(match error {
+                    Some(error) => format!("{error}, items requeued"),
+                    None => "items requeued".to_owned(),
+                }));
+            }
+        };
+        if let Some(previous) = &statement_timeout {
+            restore_statements(&mut tx, previous).await?;
+        }
+        let messages = self.settle(&mut tx, &ids, &flows, None).await?;
+        tx.commit().await?;
+
+        let mut handled = ids.iter().zip(flows).map(|((id, _), flow)| match flow {
+            ProcessFlow::Success => Outcome::Success,
+            ProcessFlow::Fail(message) => Outcome::Fail(message),
+            ProcessFlow::Requeue => match messages.get(id) {
+                Some(Some(message)) => Outcome::Fail(message.clone()),
+                _ => Outcome::Requeue,
+            },
+        });
+        Ok(outcomes
+            .into_iter()
+            .filter_map(|outcome| outcome.or_else(|| handled.next()))
+            .collect())
+    }
+
+    /// Dequeues the next item without keeping a transaction open while it is processed. The item is marked
+    /// 'in-progress' and leased for `lease`, after which it is committed immediately. Returns `None` if the queue is
+    /// empty.
+    ///
+    /// The caller must settle the item using [`Queue::ack`], [`Queue::nack`] or [`Queue::fail`] before the lease
+    /// expires, extending it with [`Queue::heartbeat`] if processing takes longer. Items whose lease expired can be
+    /// dequeued again by any worker, as if they were 'ready'.
+    ///
+    /// Every dequeue counts as an attempt. Items that ran out of attempts, as well as items that cannot be deserialized
+    /// into `T`, are permanently marked as failed with a descriptive message, and the next item is dequeued instead.
+    pub async fn dequeue<'a, A>(&self, conn: A, lease: Duration) -> Result<Option<Leased<T>>, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        let mut conn = conn.acquire().await?;
+
+        while let Some(claimed) = self.claim(&mut conn, lease, 1).await?.pop() {
+            if let Some(message) = claimed.exhausted() {
+                self.set_failed(&mut conn, claimed.id, claimed.attempts, &message)
+                    .await?;
+                continue;
+            }
+            match serde_json::from_value(claimed.item) {
+                Ok(item) => {
+                    return Ok(Some(Leased {
+                        id: claimed.id,
+                        item,
+                        attempts: claimed.attempts,
+                    }))
+                }
+                Err(error) => {
+                    let message = format!("unable to deserialize item: {error}");
+                    self.set_failed(&mut conn, claimed.id, claimed.attempts, &message)
+                        .await?;
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Completes a dequeued item according to the queue's [`CompletionPolicy`]. Returns `false` if the item is not
+    /// leased to the caller anymore, because its lease expired and it was dequeued again or settled by another worker.
+    ///
+    /// Like every method settling a [`Leased`] item, `ack` only applies to the attempt that dequeued it: once another
+    /// worker dequeued the item again, the item is theirs to settle.
+    pub async fn ack<'a, A>(&self, conn: A, leased: &Leased<T>) -> Result<bool, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        let mut conn = conn.acquire().await?;
+        Ok(self
+            .set_completed(&mut conn, leased.id, leased.attempts)
+            .await?)
+    }
+
+    /// Requeues a dequeued item to be retried according to the queue's [`RetryPolicy`], or marks it as failed if this
+    /// was its last attempt. Returns `false` if the item is not leased to the caller anymore.
+    pub async fn nack<'a, A>(&self, conn: A, leased: &Leased<T>) -> Result<bool, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        let mut conn = conn.acquire().await?;
+        let delay = self.retry.delay(leased.attempts);
+        Ok(self
+            .requeue(&mut conn, leased.id, leased.attempts, delay, None)
+            .await?
+            .is_some())
+    }
+
+    /// Extends the lease of a dequeued item to `lease` from now, signalling that it is still being processed. Returns
+    /// `false` if the item is not leased to the caller anymore, in which case processing should be abandoned.
+    pub async fn heartbeat<'a, A>(
+        &self,
+        conn: A,
+        leased: &Leased<T>,
+        lease: Duration,
+    ) -> Result<bool, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        let mut conn = conn.acquire().await?;
+        let result = sqlx::query(&self.config.render(
+            "
+            UPDATE {{queue}}
+            SET locked_until = now() + $3
+            WHERE id = $1 AND attempts = $2 AND status = 'in-progress'",
+        ))
+        .bind(leased.id)
+        .bind(leased.attempts)
+        .bind(interval(lease))
+        .execute(&mut *conn)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Permanently marks a dequeued item as failed. Returns `false` if the item is not leased to the caller anymore.
+    pub async fn fail<'a, A>(
+        &self,
+        conn: A,
+        leased: &Leased<T>,
+        message: &str,
+    ) -> Result<bool, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        let mut conn = conn.acquire().await?;
+        Ok(self
+            .set_failed(&mut conn, leased.id, leased.attempts, message)
+            .await?)
+    }
 
-        let row = query!(
+    /// Marks up to `limit` next items as 'in-progress' for `lease`, counting an attempt. Ready items that are due are
+    /// claimed in order of priority and due time, as well as in-progress items whose lease has expired. The items are
+    /// returned in the same order. Only items of the queue's kind are claimed, if it has one.
+    async fn claim(
+        &self,
+        conn: &mut PgConnection,
+        lease: Duration,
+        limit: i64,
+    ) -> Result<Vec<Claimed>, sqlx::Error> {
+        // Aging is rendered into the query rather than bound, so that queues without aging keep a plan using the index.
+        let priority = match self.aging {
+            None => "priority".to_owned(),
+            Some(aging) => format!(
+                "priority + floor(greatest(extract(epoch FROM now() - run_after), 0) / {})::bigint",
+                aging.as_secs_f64().max(1e-6)
+            ),
+        };
+        // Ready and expired items are looked up separately, so that ready items are found through the partial index.
+        let sql = self.config.render(
             "
-            UPDATE queue
-            SET status = 'in-progress'
-            WHERE id = (
-              SELECT id
-              FROM queue
-              ORDER BY id ASC
-              WHERE status = 0
+            WITH ready AS (
+              SELECT id, {priority} AS priority, run_after
+              FROM {{queue}}
+              WHERE queue = $1 AND status = 'ready' AND run_after <= now() {kind}
+              ORDER BY {priority} DESC, run_after ASC, id ASC
               FOR UPDATE SKIP LOCKED
-              LIMIT 1
+              LIMIT $3
+            ),
+            expired AS (
+              SELECT id, {priority} AS priority, run_after
+              FROM {{queue}}
+              WHERE queue = $1 AND status = 'in-progress' AND locked_until < now() {kind}
+              ORDER BY {priority} DESC, run_after ASC, id ASC
+              FOR UPDATE SKIP LOCKED
+              LIMIT $3
+            ),
+            next AS (
+              SELECT * FROM ready
+              UNION ALL
+              SELECT * FROM expired
+              ORDER BY priority DESC, run_after ASC, id ASC
+              LIMIT $3
+            ),
+            claimed AS (
+              UPDATE {{queue}}
+              SET status = 'in-progress', locked_until = now() + $2, attempts = attempts + 1
+              WHERE id IN (SELECT id FROM next)
+              RETURNING id, kind, item, attempts, max_attempts, priority, timeout, run_after
             )
-            RETURNING *;",
-        )
-        .fetch_one(&mut tx)
+            SELECT id, kind, item, attempts, max_attempts, extract(epoch FROM timeout)::float8 AS timeout_secs
+            FROM claimed
+            ORDER BY {priority} DESC, run_after ASC, id ASC",
+        );
+        let kind = match self.kind {
+            Some(_) => "AND kind = $4",
+            None => "",
+        };
+        let sql = sql.replace("{priority}", &priority).replace("{kind}", kind);
+        let query = query_as(&sql)
+            .bind(&self.name)
+            .bind(interval(lease))
+            .bind(limit);
+        match self.kind {
+            Some(kind) => query.bind(kind).fetch_all(conn).await,
+            None => query.fetch_all(conn).await,
+        }
+    }
+
+    /// Makes an in-progress item 'ready' again after `delay`, or marks it as failed if it ran out of attempts.
+    /// Returns `None` if the item was not settled, and the failure message in the latter case. `error` is stored as
+    /// the item's message, describing why the attempt failed.
+    ///
+    /// Like the other methods settling an item, `requeue` only applies to the attempt `attempts` of the item, so that a
+    /// worker whose lease expired cannot settle an item dequeued again by another worker.
+    async fn requeue(
+        &self,
+        conn: &mut PgConnection,
+        id: i64,
+        attempts: i32,
+        delay: Duration,
+        error: Option<&str>,
+    ) -> Result<Option<Option<String>>, sqlx::Error> {
+        let message: Option<(Option<String>,)> = query_as(&self.config.render(
+            "
+            UPDATE {{queue}}
+            SET status = CASE WHEN attempts < max_attempts THEN 'ready' ELSE 'failed' END::{{status}},
+                message = CASE
+                  WHEN attempts < max_attempts THEN $3
+                  ELSE concat_ws(', ', format('gave up after %s attempts', attempts), $3)
+                END,
+                locked_until = NULL,
+                run_after = now() + $2
+            WHERE id = $1 AND attempts = $4 AND status = 'in-progress'
+            RETURNING CASE WHEN status = 'failed' THEN message END",
+        ))
+        .bind(id)
+        .bind(interval(delay))
+        .bind(error)
+        .bind(attempts)
+        .fetch_optional(conn)
         .await?;
+        Ok(message.map(|(message,)| message))
+    }
 
-        match f(row)? {
-            ProcessFlow::Fail(error) => {
-                // Insert error message in the queue
-                todo!()
+    /// Applies `flows` to the in-progress items `ids`, given as (id, attempts), in a single statement. Successful items
+    /// are completed according to the [`CompletionPolicy`], and requeued items behave like in `requeue`, with `error`
+    /// as their message. Returns the message of every item that was not completed, which is `None` for requeued items.
+    async fn settle(
+        &self,
+        conn: &mut PgConnection,
+        ids: &[(i64, i32)],
+        flows: &[ProcessFlow],
+        error: Option<&str>,
+    ) -> Result<HashMap<i64, Option<String>>, sqlx::Error> {
+        let completed = match self.completion {
+            CompletionPolicy::Complete => {
+                "
+                completed AS (
+                  UPDATE {{queue}}
+                  SET status = 'completed', completed_at = now(), message = NULL, locked_until = NULL
+                  WHERE (id, attempts) IN (SELECT id, attempts FROM flows WHERE flow = 'success')
+                    AND status = 'in-progress'
+                )"
             }
-            ProcessFlow::Success => {
-                tx.commit().await?;
+            CompletionPolicy::Delete => {
+                "
+                completed AS (
+                  DELETE FROM {{queue}}
+                  WHERE (id, attempts) IN (SELECT id, attempts FROM flows WHERE flow = 'success')
+                    AND status = 'in-progress'
+                )"
             }
-            ProcessFlow::Requeue => {
-                tx.rollback().await?;
+            CompletionPolicy::Archive => {
+                "
+                deleted AS (
+                  DELETE FROM {{queue}}
+                  WHERE (id, attempts) IN (SELECT id, attempts FROM flows WHERE flow = 'success')
+                    AND status = 'in-progress'
+                  RETURNING id, queue, kind, item
+                ),
+                completed AS (
+                  INSERT INTO {{queue_archive}} (id, queue, kind, item, completed_at)
+                  SELECT id, queue, kind, item, now()
+                  FROM deleted
+                )"
             }
+        };
+        let sql = "
+            WITH flows AS (
+              SELECT *
+              FROM unnest($1::bigint[], $5::int[], $2::text[], $3::text[], $4::interval[])
+                AS flow (id, attempts, flow, message, delay)
+            ),
+            settled AS (
+              UPDATE {{queue}} claimed
+              SET status = CASE
+                    WHEN flow.flow = 'requeue' AND claimed.attempts < claimed.max_attempts THEN 'ready'
+                    ELSE 'failed'
+                  END::{{status}},
+                  message = CASE
+                    WHEN flow.flow = 'fail' THEN flow.message
+                    WHEN claimed.attempts < claimed.max_attempts THEN flow.message
+                    ELSE concat_ws(', ', format('gave up after %s attempts', claimed.attempts), flow.message)
+                  END,
+                  locked_until = NULL,
+                  run_after = now() + flow.delay
+              FROM flows flow
+              WHERE claimed.id = flow.id
+                AND claimed.attempts = flow.attempts
+                AND flow.flow != 'success'
+                AND claimed.status = 'in-progress'
+              RETURNING claimed.id, CASE WHEN claimed.status = 'failed' THEN claimed.message END AS message
+            ),
+            {completed}
+            SELECT id, message FROM settled";
+
+        let mut kinds = Vec::with_capacity(flows.len());
+        let mut messages = Vec::with_capacity(flows.len());
+        let mut delays = Vec::with_capacity(flows.len());
+        for ((_, attempts), flow) in ids.iter().zip(flows) {
+            let (kind, message) = match flow {
+                ProcessFlow::Success => ("success", None),
+                ProcessFlow::Requeue => ("requeue", error),
+                ProcessFlow::Fail(message) => ("fail", Some(message.as_str())),
+            };
+            kinds.push(kind);
+            messages.push(message);
+            delays.push(interval(self.retry.delay(*attempts)));
         }
+        let settled: Vec<(i64, Option<String>)> =
+            query_as(&self.config.render(&sql.replace("{completed}", completed)))
+                .bind(ids.iter().map(|(id, _)| *id).collect::<Vec<_>>())
+                .bind(kinds)
+                .bind(messages)
+                .bind(delays)
+                .bind(
+                    ids.iter()
+                        .map(|(_, attempts)| *attempts)
+                        .collect::<Vec<_>>(),
+                )
+                .fetch_all(conn)
+                .await?;
+        Ok(settled.into_iter().collect())
+    }
+
+    async fn set_completed(
+        &self,
+        conn: &mut PgConnection,
+        id: i64,
+        attempts: i32,
+    ) -> Result<bool, sqlx::Error> {
+        let sql = match self.completion {
+            CompletionPolicy::Complete => {
+                "
+                UPDATE {{queue}}
+                SET status = 'completed', completed_at = now(), message = NULL, locked_until = NULL
+                WHERE id = $1 AND attempts = $2 AND status = 'in-progress'"
+            }
+            CompletionPolicy::Delete => {
+                "DELETE FROM {{queue}} WHERE id = $1 AND attempts = $2 AND status = 'in-progress'"
+            }
+            CompletionPolicy::Archive => {
+                "
+                WITH completed AS (
+                  DELETE FROM {{queue}}
+                  WHERE id = $1 AND attempts = $2 AND status = 'in-progress'
+                  RETURNING id, queue, kind, item
+                )
+                INSERT INTO {{queue_archive}} (id, queue, kind, item, completed_at)
+                SELECT id, queue, kind, item, now()
+                FROM completed"
+            }
+        };
+        let result = sqlx::query(&self.config.render(sql))
+            .bind(id)
+            .bind(attempts)
+            .execute(conn)
+            .await?;
+        Ok(result.rows_affected() == 1)
     }
+
+    async fn set_failed(
+        &self,
+        conn: &mut PgConnection,
+        id: i64,
+        attempts: i32,
+        message: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(&self.config.render(
+            "
+            UPDATE {{queue}}
+            SET status = 'failed', message = $3, locked_until = NULL
+            WHERE id = $1 AND attempts = $2 AND status = 'in-progress'",
+        ))
+        .bind(id)
+        .bind(attempts)
+        .bind(message)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct Claimed {
+    id: i64,
+    kind: Option<String>,
+    item: Value,
+    attempts: i32,
+    max_attempts: i32,
+    timeout_secs: Option<f64>,
+}
+
+impl Claimed {
+    /// The item's own timeout, see [`EnqueueOptions::timeout`].
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout_secs
+            .map(|secs| Duration::from_secs_f64(secs.max(0.0)))
+    }
+
+    /// The failure message if the item was dequeued more often than allowed, which happens when a worker stops
+    /// processing the item during its last attempt.
+    fn exhausted(&self) -> Option<String> {
+        (self.attempts > self.max_attempts)
+            .then(|| format!("gave up after {} attempts", self.max_attempts))
+    }
+}
+
+/// Per-item overrides for [`Queue::enqueue_with`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnqueueOptions {
+    /// The item's priority, instead of the queue's default priority (see [`Queue::with_priority`]).
+    pub priority: Option<i32>,
+    /// The item's max attempts, instead of the queue's (see [`Queue::with_max_attempts`]).
+    pub max_attempts: Option<i32>,
+    /// The item is not processed before this time. Defaults to the current time.
+    pub at: Option<DateTime<Utc>>,
+    /// The item is not processed before `delay` has passed after `at`.
+    pub delay: Duration,
+    /// The item's timeout, instead of the timeout of its [`Job`] or queue (see [`Queue::with_timeout`]).
+    pub timeout: Option<Duration>,
+}
+
+/// An item dequeued by [`Queue::dequeue`], leased to the caller until it is settled or the lease expires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leased<T> {
+    pub id: i64,
+    pub item: T,
+    /// The number of times the item has been dequeued, including this time. Identifies the lease: the item can only
+    /// be settled with this [`Leased`] until it is dequeued again.
+    pub attempts: i32,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ProcessFlow {
     Success,
     Requeue,
     Fail(String),
 }
+
+/// What happens to items once they have been processed successfully.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompletionPolicy {
+    /// Keep the item, with status 'completed' and `completed_at` set.
+    #[default]
+    Complete,
+    /// Delete the item.
+    Delete,
+    /// Move the item to the `queue_archive` table.
+    Archive,
+}
+
+/// What happened to the item handled by [`Queue::process`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    Requeue,
+    Fail(String),
+    /// The item could not be deserialized, or no handler is registered for its kind, and it has been marked as failed.
+    /// Retrying would yield the same result.
+    Malformed(String),
+}
+
+/// Appends `value` to `buf`, escaped for a column of `COPY`'s text format.
+fn copy_text(value: &str, buf: &mut String) {
+    for c in value.chars() {
+        match c {
+            '\\' => buf.push_str("\\\\"),
+            '\t' => buf.push_str("\\t"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            c => buf.push(c),
+        }
+    }
+}
+
+/// Awaits the handler `handle` for up to `timeout`, catching its panics. Returns `None` if it timed out.
+///
+/// A handler also times out when it fails after `timeout`, which happens when its statement is cancelled by
+/// [`limit_statements`] right before the timeout fires here.
+async fn handle_within<R>(
+    timeout: Option<Duration>,
+    handle: impl Future<Output = std::thread::Result<Result<R, ()>>>,
+) -> Option<std::thread::Result<Result<R, ()>>> {
+    let Some(timeout) = timeout else {
+        return Some(handle.await);
+    };
+    let started = Instant::now();
+    match tokio::time::timeout(timeout, handle).await {
+        Ok(Ok(Err(()))) if started.elapsed() >= timeout => None,
+        handled => handled.ok(),
+    }
+}
+
+/// Cancels the statements of the current transaction that run for longer than `timeout`, returning the previous
+/// `statement_timeout` to restore with [`restore_statements`].
+///
+/// Dropping a timed out handler does not stop the statement it is running, and rolling back its transaction waits for
+/// that statement, e.g. one blocked on a lock. Limiting every statement to the handler's timeout bounds that wait, so
+/// that an abandoned attempt releases its transaction at most twice the timeout after it started.
+async fn limit_statements(
+    conn: &mut PgConnection,
+    timeout: Duration,
+) -> Result<String, sqlx::Error> {
+    let (previous,): (String,) = query_as("SELECT current_setting('statement_timeout')")
+        .fetch_one(&mut *conn)
+        .await?;
+    // A zero `statement_timeout` disables it, and values above i32::MAX milliseconds are rejected.
+    let millis = timeout.as_millis().clamp(1, i32::MAX as u128);
+    sqlx::query("SELECT set_config('statement_timeout', $1, true)")
+        .bind(format!("{millis}ms"))
+        .execute(conn)
+        .await?;
+    Ok(previous)
+}
+
+/// Restores the `statement_timeout` of the current transaction once the handler returned, so that settling the items
+/// is not limited by the handler's timeout.
+async fn restore_statements(conn: &mut PgConnection, previous: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT set_config('statement_timeout', $1, true)")
+        .bind(previous)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Converts `duration` to an interval, truncating it to microseconds.
+pub(crate) fn interval(duration: Duration) -> PgInterval {
+    PgInterval {
+        months: 0,
+        days: 0,
+        microseconds: duration.as_micros().try_into().unwrap_or(i64::MAX),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn copied(value: &str) -> String {
+        let mut buf = String::new();
+        copy_text(value, &mut buf);
+        buf
+    }
+
+    #[test]
+    fn copy_text_escapes() {
+        assert_eq!(copied("plain"), "plain");
+        assert_eq!(copied(r"a\b"), r"a\\b");
+        assert_eq!(copied("a\tb\nc\rd"), r"a\tb\nc\rd");
+        assert_eq!(copied(r#"{"a":"b\n"}"#), r#"{"a":"b\\n"}"#);
+        assert_eq!(copied("é ✓"), "é ✓");
+    }
+
+    #[test]
+    fn copy_text_appends() {
+        let mut buf = "1\t".to_owned();
+        copy_text("a\tb", &mut buf);
+        assert_eq!(buf, r"1	a\tb");
+    }
+}
@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::postgres::PgListener;
+use sqlx::{query_as, PgConnection, PgPool};
+
+use crate::{Error, Handler, Outcome, Queue, QueueConfig};
+
+/// Waits for work on a single queue, created by [`Queue::listen`].
+///
+/// Enqueueing an item notifies the queue's channel once the item is committed, which wakes up every listener of the
+/// queue. Notifications are not delivered while the listener reconnects, so listeners also wake up after a fallback
+/// poll interval, or when the next delayed or retried item becomes due.
+pub struct Listener {
+    listener: PgListener,
+    next_due: String,
+    name: String,
+    kind: Option<&'static str>,
+    poll: Duration,
+}
+
+impl Listener {
+    /// Waits until the queue may have a ready item: a notification was received, an item that was not due yet
+    /// became due, or the poll interval passed. Returns immediately if an item is due already.
+    ///
+    /// Wakeups can be spurious, for example when another worker dequeued the item first.
+    pub async fn wait_for_work(&mut self) -> Result<(), Error> {
+        let (now, next_due): (DateTime<Utc>, Option<DateTime<Utc>>) = query_as(&self.next_due)
+            .bind(&self.name)
+            .bind(self.kind)
+            .fetch_one(&mut self.listener)
+            .await?;
+        let timeout = match next_due {
+            Some(due) => (due - now)
+                .to_std()
+                .unwrap_or(Duration::ZERO)
+                .min(self.poll),
+            None => self.poll,
+        };
+        if timeout.is_zero() {
+            return Ok(());
+        }
+
+        match tokio::time::timeout(timeout, self.listener.recv()).await {
+            Ok(notification) => notification.map(|_| ()).map_err(Into::into),
+            Err(_elapsed) => Ok(()),
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Queue<T> {
+    /// Listens for items enqueued on this queue, falling back to checking for items every `poll`. See [`Listener`].
+    /// Items of other kinds than the queue's (see [`Queue::kind`]) only cause spurious wakeups.
+    pub async fn listen(&self, pool: &PgPool, poll: Duration) -> Result<Listener, Error> {
+        let mut listener = PgListener::connect_with(pool).await?;
+        let (channel,): (String,) = query_as("SELECT 'pg_queue_' || md5($1)")
+            .bind(self.config.channel_key(&self.name))
+            .fetch_one(&mut listener)
+            .await?;
+        listener.listen(&channel).await?;
+
+        Ok(Listener {
+            listener,
+            // Expired leases are not considered: reclaiming them is left to the fallback poll. Ready items are found
+            // through the index on (queue, run_after), or the one on their kind.
+            next_due: self.config.render(
+                "
+                SELECT now(), min(run_after)
+                FROM {{queue}}
+                WHERE queue = $1 AND status = 'ready' AND ($2::text IS NULL OR kind = $2)",
+            ),
+            name: self.name.clone(),
+            kind: self.kind,
+            poll,
+        })
+    }
+
+    /// Processes items with `f` as they are enqueued, passing the result of every [`Queue::process`] to `report`.
+    /// Waits for new items using a [`Listener`] whenever the queue is empty, and for `poll` after errors other than
+    /// [`Error::Handler`], e.g. while the database is unreachable. Runs until the future is dropped.
+    pub async fn run<H>(
+        &self,
+        pool: &PgPool,
+        poll: Duration,
+        f: H,
+        mut report: impl FnMut(Result<Outcome, Error>),
+    ) where
+        H: Handler<T> + Clone,
+    {
+        let mut listener: Option<Listener> = None;
+        loop {
+            loop {
+                match self.process(pool, f.clone()).await {
+                    Ok(Some(outcome)) => report(Ok(outcome)),
+                    Ok(None) => break,
+                    // Handler errors only concern a single item, so move on to the next one.
+                    Err(error @ Error::Handler(_)) => report(Err(error)),
+                    Err(error) => {
+                        report(Err(error));
+                        tokio::time::sleep(poll).await;
+                        break;
+                    }
+                }
+            }
+
+            let waited = match &mut listener {
+                Some(listener) => listener.wait_for_work().await,
+                None => self.listen(pool, poll).await.map(|connected| {
+                    listener = Some(connected);
+                }),
+            };
+            if let Err(error) = waited {
+                report(Err(error));
+                listener = None;
+                tokio::time::sleep(poll).await;
+            }
+        }
+    }
+}
+
+/// Notifies the listeners of the queue `name` once the current transaction commits.
+pub(crate) async fn notify(
+    conn: &mut PgConnection,
+    config: &QueueConfig,
+    name: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT pg_notify('pg_queue_' || md5($1), '')")
+        .bind(config.channel_key(name))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use sqlx::migrate::{MigrateError, Migration, Migrator};
+use sqlx::{query, query_as, raw_sql, Acquire, PgConnection, Postgres};
+
+use crate::{Error, QueueConfig};
+
+/// The crate's migrations (defaults to "./migrations").
+///
+/// Object names in the migrations are `{{name}}` placeholders, so these cannot be applied as-is by a regular sqlx
+/// migrator. Use [`migrate`], which renders them for a [`QueueConfig`].
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Applies all pending migrations for `config`.
+///
+/// Applied migrations are recorded in the `pg_queue_migrations` table of the configured schema (prefixed like every
+/// other object), so they never clash with the `_sqlx_migrations` history of the host application. Concurrent calls
+/// are serialized using an advisory lock.
+pub async fn migrate<'a, A>(conn: A, config: &QueueConfig) -> Result<(), Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut conn = conn.acquire().await?;
+    let history = config.ident("pg_queue_migrations");
+
+    query("SELECT pg_advisory_lock(hashtext($1))")
+        .bind(&history)
+        .execute(&mut *conn)
+        .await?;
+    let result = apply(&mut conn, config, &history).await;
+    query("SELECT pg_advisory_unlock(hashtext($1))")
+        .bind(&history)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(result?)
+}
+
+/// The versions of the migrations that have not been applied yet for `config`. The schema is up to date if this is
+/// empty.
+///
+/// Fails with [`Error::SchemaMismatch`] if the database contains migrations unknown to this version of the crate, or if
+/// an applied migration differs from the one shipped with the crate.
+pub async fn pending_migrations<'a, A>(conn: A, config: &QueueConfig) -> Result<Vec<i64>, Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut conn = conn.acquire().await?;
+    let history = config.ident("pg_queue_migrations");
+
+    let (exists,): (bool,) = query_as("SELECT to_regclass($1) IS NOT NULL")
+        .bind(&history)
+        .fetch_one(&mut *conn)
+        .await?;
+    let migrations = migrations(config);
+    if !exists {
+        return Ok(migrations.iter().map(|m| m.version).collect());
+    }
+
+    Ok(pending(&mut conn, &migrations, &history).await?)
+}
+
+async fn apply(
+    conn: &mut PgConnection,
+    config: &QueueConfig,
+    history: &str,
+) -> Result<(), MigrateError> {
+    raw_sql(&config.render(
+        "
+        CREATE SCHEMA IF NOT EXISTS {{schema}};
+        CREATE TABLE IF NOT EXISTS {{pg_queue_migrations}} (
+            version BIGINT PRIMARY KEY,
+            description TEXT NOT NULL,
+            installed_on TIMESTAMPTZ NOT NULL DEFAULT now(),
+            checksum BYTEA NOT NULL
+        );",
+    ))
+    .execute(&mut *conn)
+    .await?;
+
+    let migrations = migrations(config);
+    let pending = pending(conn, &migrations, history).await?;
+
+    for migration in migrations.iter().filter(|m| pending.contains(&m.version)) {
+        // The migration and its bookkeeping share a transaction, so a migration is never applied twice.
+        let mut tx = conn.begin().await?;
+        raw_sql(&migration.sql).execute(&mut *tx).await?;
+        query(&format!(
+            "INSERT INTO {history} (version, description, checksum) VALUES ($1, $2, $3)"
+        ))
+        .bind(migration.version)
+        .bind(&*migration.description)
+        .bind(&*migration.checksum)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+async fn pending(
+    conn: &mut PgConnection,
+    migrations: &[Migration],
+    history: &str,
+) -> Result<Vec<i64>, MigrateError> {
+    let applied: Vec<(i64, Vec<u8>)> = query_as(&format!(
+        "SELECT version, checksum FROM {history} ORDER BY version"
+    ))
+    .fetch_all(conn)
+    .await?;
+
+    for (version, checksum) in &applied {
+        match migrations.iter().find(|m| m.version == *version) {
+            None => return Err(MigrateError::VersionMissing(*version)),
+            Some(m) if *m.checksum != **checksum => {
+                return Err(MigrateError::VersionMismatch(*version))
+            }
+            Some(_) => {}
+        }
+    }
+
+    Ok(migrations
+        .iter()
+        .map(|m| m.version)
+        .filter(|version| !applied.iter().any(|(applied, _)| applied == version))
+        .collect())
+}
+
+/// The crate's migrations, rendered for `config`.
+fn migrations(config: &QueueConfig) -> Vec<Migration> {
+    MIGRATOR
+        .iter()
+        .map(|migration| {
+            Migration::new(
+                migration.version,
+                migration.description.clone(),
+                migration.migration_type,
+                config.render(&migration.sql).into(),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use sqlx::{Acquire, PgPool, Postgres};
+
+use crate::{Error, QueueConfig};
+
+/// Returns items stranded 'in-progress' to 'ready', for every queue stored according to a [`QueueConfig`].
+///
+/// An item is stranded when its lease expired without the worker sending a heartbeat (see [`Queue::heartbeat`]), or
+/// when it is 'in-progress' without a lease nor a processing transaction holding its lock, for example after a manual
+/// intervention. Items locked by a running [`Queue::process`] are never touched.
+///
+/// Reaping does not count an attempt: attempts are counted when items are dequeued, so the attempt of the crashed
+/// worker has been counted already. Reaped items keep "lease expired" as their message until they are processed again.
+///
+/// [`Queue::heartbeat`]: crate::Queue::heartbeat
+/// [`Queue::process`]: crate::Queue::process
+#[derive(Debug, Clone)]
+pub struct Reaper {
+    config: QueueConfig,
+    interval: Duration,
+}
+
+impl Reaper {
+    /// A reaper that checks for stranded items every `interval` in [`Reaper::run`].
+    pub fn new(config: QueueConfig, interval: Duration) -> Self {
+        Self { config, interval }
+    }
+
+    /// Makes all currently stranded items 'ready' again, or marks them as failed if they ran out of attempts. Returns
+    /// the number of reaped items.
+    pub async fn reap<'a, A>(&self, conn: A) -> Result<u64, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        let mut conn = conn.acquire().await?;
+        let result = sqlx::query(&self.config.render(
+            "
+            UPDATE {{queue}}
+            SET status = CASE WHEN attempts < max_attempts THEN 'ready' ELSE 'failed' END::{{status}},
+                message = CASE
+                  WHEN attempts < max_attempts THEN 'lease expired'
+                  ELSE concat_ws(', ', format('gave up after %s attempts', attempts), 'lease expired')
+                END,
+                locked_until = NULL
+            WHERE id IN (
+              SELECT id
+              FROM {{queue}}
+              WHERE status = 'in-progress' AND (locked_until IS NULL OR locked_until < now())
+              FOR UPDATE SKIP LOCKED
+            )",
+        ))
+        .execute(&mut *conn)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Reaps every `interval`, passing the result of each pass to `report`. Runs until the future is dropped.
+    pub async fn run(&self, pool: &PgPool, mut report: impl FnMut(Result<u64, Error>)) {
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            report(self.reap(pool).await);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+/// How long a requeued item waits before it can be dequeued again.
+///
+/// Delays are computed from the number of attempts made so far, which is at least 1 when an item is requeued.
+#[derive(Debug, Clone, Copy)]
+pub enum RetryPolicy {
+    /// Always wait the same delay. `Fixed(Duration::ZERO)` retries immediately.
+    Fixed(Duration),
+    /// Wait `base`, doubling with every attempt, up to `max`.
+    Exponential { base: Duration, max: Duration },
+    /// Wait a random delay between zero and the [`RetryPolicy::Exponential`] delay, which spreads out retries of items
+    /// that failed at the same time.
+    ExponentialJitter { base: Duration, max: Duration },
+    /// Compute the delay from the number of attempts.
+    Custom(fn(i32) -> Duration),
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::Exponential {
+            base: Duration::from_secs(1),
+            max: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before retrying an item that has been attempted `attempts` times.
+    pub fn delay(&self, attempts: i32) -> Duration {
+        match *self {
+            Self::Fixed(delay) => delay,
+            Self::Exponential { base, max } => exponential(base, max, attempts),
+            Self::ExponentialJitter { base, max } => {
+                rand::thread_rng().gen_range(Duration::ZERO..=exponential(base, max, attempts))
+            }
+            Self::Custom(delay) => delay(attempts),
+        }
+    }
+}
+
+fn exponential(base: Duration, max: Duration, attempts: i32) -> Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 31) as u32;
+    base.saturating_mul(2u32.pow(exponent)).min(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn fixed() {
+        let policy = RetryPolicy::Fixed(SECOND);
+        assert_eq!(policy.delay(1), SECOND);
+        assert_eq!(policy.delay(10), SECOND);
+    }
+
+    #[test]
+    fn exponential() {
+        let policy = RetryPolicy::Exponential {
+            base: SECOND,
+            max: 60 * SECOND,
+        };
+        assert_eq!(policy.delay(0), SECOND);
+        assert_eq!(policy.delay(1), SECOND);
+        assert_eq!(policy.delay(2), 2 * SECOND);
+        assert_eq!(policy.delay(4), 8 * SECOND);
+        assert_eq!(policy.delay(7), 60 * SECOND);
+        assert_eq!(policy.delay(i32::MAX), 60 * SECOND);
+    }
+
+    #[test]
+    fn exponential_saturates() {
+        let policy = RetryPolicy::Exponential {
+            base: Duration::MAX,
+            max: Duration::MAX,
+        };
+        assert_eq!(policy.delay(40), Duration::MAX);
+    }
+
+    #[test]
+    fn exponential_jitter() {
+        let (base, max) = (SECOND, 60 * SECOND);
+        let bound = RetryPolicy::Exponential { base, max };
+        let policy = RetryPolicy::ExponentialJitter { base, max };
+        for attempts in 1..10 {
+            assert!(policy.delay(attempts) <= bound.delay(attempts));
+        }
+    }
+
+    #[test]
+    fn custom() {
+        let policy = RetryPolicy::Custom(|attempts| Duration::from_millis(attempts as u64 * 10));
+        assert_eq!(policy.delay(3), Duration::from_millis(30));
+    }
+}
@@ -0,0 +1,390 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::postgres::types::PgInterval;
+use sqlx::{query_as, Acquire, PgPool, Postgres};
+
+use crate::{interval, Error, Queue, QueueConfig};
+
+/// A recurring item, enqueued every time its cron expression fires. Registered with [`Queue::schedule`] and enqueued
+/// by a [`Scheduler`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule<T> {
+    /// Identifies the schedule among the schedules of its queue. Scheduling under an existing name replaces that
+    /// schedule, while other queues may use the same name for schedules of their own.
+    pub name: String,
+    /// Cron expression including seconds, e.g. `0 30 9 * * Mon-Fri` for 9:30 on weekdays.
+    pub cron: String,
+    /// The timezone in which `cron` is evaluated.
+    pub timezone: Tz,
+    pub item: T,
+    pub misfire: Misfire,
+    /// The timeout of the enqueued items, instead of the timeout of their [`Job`](crate::Job) or queue. See
+    /// [`EnqueueOptions::timeout`](crate::EnqueueOptions::timeout).
+    pub timeout: Option<Duration>,
+}
+
+/// What a [`Scheduler`] does with occurrences that were missed, for example because no scheduler was running. An
+/// occurrence is missed if it is due for longer than twice the scheduler's interval, so that occurrences are not
+/// missed when a tick runs slightly late.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Misfire {
+    /// Drop missed occurrences.
+    Skip,
+    /// Enqueue a single item for all missed occurrences.
+    #[default]
+    RunOnce,
+    /// Enqueue an item for every missed occurrence.
+    RunAll,
+}
+
+impl Misfire {
+    /// The value of the `misfire` enum in the database.
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Skip => "skip",
+            Self::RunOnce => "run-once",
+            Self::RunAll => "run-all",
+        }
+    }
+
+    fn parse(misfire: &str) -> Result<Self, Error> {
+        [Self::Skip, Self::RunOnce, Self::RunAll]
+            .into_iter()
+            .find(|m| m.as_str() == misfire)
+            .ok_or_else(|| Error::InvalidSchedule(format!("unknown misfire policy: {misfire}")))
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Queue<T> {
+    /// Registers a recurring item on this queue with the queue's max attempts, priority and kind, or replaces the
+    /// queue's schedule with the same name.
+    ///
+    /// A replaced schedule keeps its next run time unless its cron expression or timezone changed, so that
+    /// re-registering schedules on startup does not drop the occurrences missed while the application was down.
+    pub async fn schedule<'a, A>(&self, conn: A, schedule: Schedule<T>) -> Result<(), Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        let cron = cron::Schedule::from_str(&schedule.cron).map_err(invalid)?;
+        let next_run_at = cron
+            .after(&Utc::now().with_timezone(&schedule.timezone))
+            .next()
+            .ok_or_else(|| Error::InvalidSchedule("cron expression never fires".to_owned()))?;
+        let item = serde_json::to_value(schedule.item)?;
+
+        let mut conn = conn.acquire().await?;
+        sqlx::query(&self.config.render(
+            "
+            INSERT INTO {{schedules}} AS schedule (
+              name, queue, cron, timezone, item, max_attempts, priority, kind, misfire, next_run_at, timeout
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::text::{{misfire}}, $10, $11)
+            ON CONFLICT (queue, name) DO UPDATE
+            SET cron = excluded.cron,
+                timezone = excluded.timezone,
+                item = excluded.item,
+                max_attempts = excluded.max_attempts,
+                priority = excluded.priority,
+                kind = excluded.kind,
+                misfire = excluded.misfire,
+                timeout = excluded.timeout,
+                next_run_at = CASE
+                  WHEN (schedule.cron, schedule.timezone) = (excluded.cron, excluded.timezone) THEN schedule.next_run_at
+                  ELSE excluded.next_run_at
+                END",
+        ))
+        .bind(&schedule.name)
+        .bind(&self.name)
+        .bind(&schedule.cron)
+        .bind(schedule.timezone.name())
+        .bind(item)
+        .bind(self.max_attempts)
+        .bind(self.priority)
+        .bind(self.kind)
+        .bind(schedule.misfire.as_str())
+        .bind(next_run_at.with_timezone(&Utc))
+        .bind(schedule.timeout.map(interval))
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Removes the schedule named `name` from this queue. Returns `false` if it does not exist.
+    pub async fn unschedule<'a, A>(&self, conn: A, name: &str) -> Result<bool, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        let mut conn = conn.acquire().await?;
+        let result = sqlx::query(
+            &self
+                .config
+                .render("DELETE FROM {{schedules}} WHERE name = $1 AND queue = $2"),
+        )
+        .bind(name)
+        .bind(&self.name)
+        .execute(&mut *conn)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+/// Enqueues the items of due [`Schedule`]s, for every queue stored according to a [`QueueConfig`].
+///
+/// Any number of schedulers can run against the same database: due schedules are locked while their items are
+/// enqueued, and the items are committed together with the schedule's next run time, so every occurrence is enqueued
+/// exactly once.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    config: QueueConfig,
+    interval: Duration,
+}
+
+#[derive(sqlx::FromRow)]
+struct Due {
+    name: String,
+    queue: String,
+    cron: String,
+    timezone: String,
+    item: Value,
+    max_attempts: i32,
+    priority: i32,
+    kind: Option<String>,
+    misfire: String,
+    next_run_at: DateTime<Utc>,
+    timeout: Option<PgInterval>,
+}
+
+impl Scheduler {
+    /// A scheduler that checks for due schedules every `interval` in [`Scheduler::run`].
+    pub fn new(config: QueueConfig, interval: Duration) -> Self {
+        Self { config, interval }
+    }
+
+    /// Enqueues the items of all currently due schedules, applying their [`Misfire`] policy to missed occurrences.
+    /// Returns the number of enqueued items.
+    pub async fn tick<'a, A>(&self, conn: A) -> Result<u64, Error>
+    where
+        A: Acquire<'a, Database = Postgres>,
+    {
+        let mut tx = conn.begin().await?;
+        let (now,): (DateTime<Utc>,) = query_as("SELECT now()").fetch_one(&mut *tx).await?;
+        let due: Vec<Due> = query_as(&self.config.render(
+            "
+            SELECT name, queue, cron, timezone, item, max_attempts, priority, kind, misfire::text, next_run_at, timeout
+            FROM {{schedules}}
+            WHERE next_run_at <= $1
+            FOR UPDATE SKIP LOCKED",
+        ))
+        .bind(now)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let grace = chrono::Duration::from_std(self.interval.saturating_mul(2)).map_err(invalid)?;
+        let mut enqueued = 0;
+        for schedule in due {
+            let timezone = Tz::from_str(&schedule.timezone).map_err(invalid)?;
+            let cron = cron::Schedule::from_str(&schedule.cron).map_err(invalid)?;
+            let next_run_at = schedule.next_run_at.with_timezone(&timezone);
+
+            let scheduled = occurrences(
+                &cron,
+                next_run_at,
+                now,
+                grace,
+                Misfire::parse(&schedule.misfire)?,
+            );
+
+            let result = sqlx::query(&self.config.render(
+                "
+                INSERT INTO {{queue}} (queue, kind, item, max_attempts, priority, timeout, scheduled_at)
+                SELECT $1, $6, $2, $3, $4, $7, scheduled_at
+                FROM unnest($5::timestamptz[]) scheduled_at",
+            ))
+            .bind(&schedule.queue)
+            .bind(&schedule.item)
+            .bind(schedule.max_attempts)
+            .bind(schedule.priority)
+            .bind(&scheduled)
+            .bind(&schedule.kind)
+            .bind(&schedule.timeout)
+            .execute(&mut *tx)
+            .await?;
+            enqueued += result.rows_affected();
+            if result.rows_affected() > 0 {
+                crate::listen::notify(&mut tx, &self.config, &schedule.queue).await?;
+            }
+
+            let next_run_at = cron
+                .after(&now.with_timezone(&timezone))
+                .next()
+                .map(|at| at.with_timezone(&Utc));
+            let sql = match next_run_at {
+                Some(_) => {
+                    "UPDATE {{schedules}} SET next_run_at = $3 WHERE queue = $1 AND name = $2"
+                }
+                None => "DELETE FROM {{schedules}} WHERE queue = $1 AND name = $2",
+            };
+            let sql = self.config.render(sql);
+            let query = sqlx::query(&sql).bind(&schedule.queue).bind(&schedule.name);
+            match next_run_at {
+                Some(next_run_at) => query.bind(next_run_at).execute(&mut *tx).await?,
+                None => query.execute(&mut *tx).await?,
+            };
+        }
+
+        tx.commit().await?;
+        Ok(enqueued)
+    }
+
+    /// Ticks every `interval`, passing the result of each tick to `report`. Runs until the future is dropped.
+    pub async fn run(&self, pool: &PgPool, mut report: impl FnMut(Result<u64, Error>)) {
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            report(self.tick(pool).await);
+        }
+    }
+}
+
+/// The occurrences of `cron` from `next_run_at` until `now` to enqueue, according to `misfire`. Occurrences due for
+/// longer than `grace` are missed.
+fn occurrences(
+    cron: &cron::Schedule,
+    next_run_at: DateTime<Tz>,
+    now: DateTime<Utc>,
+    grace: chrono::Duration,
+    misfire: Misfire,
+) -> Vec<DateTime<Utc>> {
+    let occurrences = std::iter::once(next_run_at)
+        .chain(cron.after(&next_run_at))
+        .take_while(|at| *at <= now)
+        .map(|at| at.with_timezone(&Utc));
+    let missed_before = now.checked_sub_signed(grace);
+    let (missed, on_time): (Vec<_>, Vec<_>) =
+        occurrences.partition(|at| missed_before.is_some_and(|before| *at < before));
+    match misfire {
+        Misfire::Skip => on_time,
+        Misfire::RunOnce => missed.last().into_iter().copied().chain(on_time).collect(),
+        Misfire::RunAll => missed.into_iter().chain(on_time).collect(),
+    }
+}
+
+fn invalid(error: impl std::fmt::Display) -> Error {
+    Error::InvalidSchedule(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn misfire_round_trips() {
+        for misfire in [Misfire::Skip, Misfire::RunOnce, Misfire::RunAll] {
+            assert_eq!(Misfire::parse(misfire.as_str()).unwrap(), misfire);
+        }
+    }
+
+    #[test]
+    fn misfire_rejects_unknown() {
+        let error = Misfire::parse("run_once").unwrap_err();
+        assert!(matches!(error, Error::InvalidSchedule(message) if message.contains("run_once")));
+    }
+
+    fn every_second() -> cron::Schedule {
+        cron::Schedule::from_str("* * * * * *").unwrap()
+    }
+
+    /// `secs` seconds and `millis` milliseconds after an arbitrary whole second.
+    fn at(secs: i64, millis: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+            + chrono::Duration::milliseconds(millis)
+    }
+
+    fn enqueued(
+        next_run_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+        misfire: Misfire,
+    ) -> Vec<DateTime<Utc>> {
+        let grace = chrono::Duration::seconds(2);
+        occurrences(
+            &every_second(),
+            next_run_at.with_timezone(&Tz::UTC),
+            now,
+            grace,
+            misfire,
+        )
+    }
+
+    #[test]
+    fn nothing_due() {
+        assert!(enqueued(at(1, 0), at(0, 999), Misfire::RunAll).is_empty());
+    }
+
+    #[test]
+    fn due_now() {
+        assert_eq!(enqueued(at(1, 0), at(1, 0), Misfire::Skip), [at(1, 0)]);
+    }
+
+    #[test]
+    fn late_tick_is_on_time() {
+        assert_eq!(
+            enqueued(at(0, 0), at(1, 3), Misfire::Skip),
+            [at(0, 0), at(1, 0)]
+        );
+        assert_eq!(
+            enqueued(at(0, 0), at(2, 0), Misfire::Skip),
+            [at(0, 0), at(1, 0), at(2, 0)]
+        );
+    }
+
+    #[test]
+    fn missed_after_grace() {
+        let (next_run_at, now) = (at(0, 0), at(4, 500));
+        assert_eq!(
+            enqueued(next_run_at, now, Misfire::Skip),
+            [at(3, 0), at(4, 0)]
+        );
+        assert_eq!(
+            enqueued(next_run_at, now, Misfire::RunOnce),
+            [at(2, 0), at(3, 0), at(4, 0)]
+        );
+        assert_eq!(
+            enqueued(next_run_at, now, Misfire::RunAll),
+            [at(0, 0), at(1, 0), at(2, 0), at(3, 0), at(4, 0)]
+        );
+    }
+
+    #[test]
+    fn run_once_without_missed() {
+        assert_eq!(enqueued(at(0, 0), at(0, 10), Misfire::RunOnce), [at(0, 0)]);
+    }
+
+    /// Ticks every second, a few milliseconds before every occurrence and with some jitter, like a scheduler whose
+    /// interval matches the schedule.
+    #[test]
+    fn aligned_ticks_enqueue_every_occurrence() {
+        let cron = every_second();
+        let mut next_run_at = at(0, 0);
+        let mut scheduled = Vec::new();
+        for (tick, jitter) in (0..8).zip([0, 4, 1, 9, 0, 6, 2, 8]) {
+            let now = at(tick, jitter - 3);
+            if next_run_at <= now {
+                let grace = chrono::Duration::seconds(2);
+                let timezone = next_run_at.with_timezone(&Tz::UTC);
+                scheduled.extend(occurrences(&cron, timezone, now, grace, Misfire::Skip));
+                next_run_at = cron.after(&now).next().unwrap();
+            }
+        }
+        assert_eq!(
+            scheduled,
+            (0..8).map(|secs| at(secs, 0)).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+use crate::{Error, Handler, Outcome, Queue, Registry};
+
+/// Runs a number of concurrent workers processing a queue with the same handler or [`Registry`], until it is shut
+/// down.
+///
+/// Workers process items with [`Queue::process`] for as long as the queue has ready items. Idle workers are woken up
+/// by a single [`Listener`](crate::Listener) shared by the pool, and check the queue every poll interval in case the
+/// listener misses a notification.
+///
+/// ```no_run
+/// # use pg_queue::{handler_fn, CancellationToken, ProcessFlow, Queue, WorkerPool};
+/// # async fn example(pool: sqlx::PgPool, queue: Queue<u64>) {
+/// # let handler = handler_fn(|_tx, _item: u64| Box::pin(async { Ok(ProcessFlow::Success) }));
+/// let workers = WorkerPool::new(queue, handler).with_concurrency(8);
+/// let shutdown = CancellationToken::new();
+/// tokio::spawn({
+///     let shutdown = shutdown.clone();
+///     async move {
+///         tokio::signal::ctrl_c().await.ok();
+///         shutdown.cancel();
+///     }
+/// });
+/// workers.run(&pool, shutdown).await;
+/// # }
+/// ```
+pub struct WorkerPool<T = Value> {
+    queue: Queue<T>,
+    process: Process,
+    concurrency: usize,
+    poll: Duration,
+    shutdown_timeout: Duration,
+    states: Arc<Mutex<Vec<WorkerState>>>,
+}
+
+/// Processes the next item of the pool's queue.
+type Process =
+    Arc<dyn Fn(PgPool) -> BoxFuture<'static, Result<Option<Outcome>, Error>> + Send + Sync>;
+
+/// What a worker of a [`WorkerPool`] is doing, along with counters since the pool started.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkerState {
+    pub status: WorkerStatus,
+    /// Items processed, whatever their [`Outcome`](crate::Outcome).
+    pub processed: u64,
+    /// Calls to [`Queue::process`] that returned an error.
+    pub errors: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WorkerStatus {
+    /// The pool is not running.
+    #[default]
+    Stopped,
+    /// Waiting for items.
+    Idle,
+    /// Dequeueing or processing an item.
+    Busy,
+    /// The worker was still processing an item when the shutdown timeout passed, and has been aborted. The item is
+    /// retried once its lease expires.
+    Aborted,
+}
+
+impl WorkerPool {
+    /// A pool of a single worker processing the untyped `queue` with the handlers of `registry`. See
+    /// [`Queue::process_jobs`].
+    pub fn with_registry(queue: Queue, registry: Registry) -> Self {
+        let (processed, registry) = (queue.clone(), Arc::new(registry));
+        Self::with_process(
+            queue,
+            Arc::new(move |pool| {
+                let (queue, registry) = (processed.clone(), registry.clone());
+                Box::pin(async move { queue.process_jobs(&pool, &registry).await })
+            }),
+        )
+    }
+}
+
+impl<T> WorkerPool<T>
+where
+    T: Serialize + DeserializeOwned + Send + 'static,
+{
+    /// A pool of a single worker processing `queue` with `handler`.
+    pub fn new<H>(queue: Queue<T>, handler: H) -> Self
+    where
+        H: Handler<T> + Clone + Send + Sync + 'static,
+    {
+        let processed = queue.clone();
+        Self::with_process(
+            queue,
+            Arc::new(move |pool| {
+                let (queue, handler) = (processed.clone(), handler.clone());
+                Box::pin(async move { queue.process(&pool, handler).await })
+            }),
+        )
+    }
+
+    fn with_process(queue: Queue<T>, process: Process) -> Self {
+        Self {
+            queue,
+            process,
+            concurrency: 1,
+            poll: Duration::from_secs(5),
+            shutdown_timeout: Duration::from_secs(30),
+            states: Arc::default(),
+        }
+    }
+
+    /// Sets the number of items processed concurrently. Defaults to 1.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets how often idle workers check the queue when no notification arrives. Defaults to 5 seconds.
+    pub fn with_poll(mut self, poll: Duration) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    /// Sets how long in-flight items may take to finish after shutdown, before their workers are aborted. Defaults to
+    /// 30 seconds.
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    pub fn queue(&self) -> &Queue<T> {
+        &self.queue
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    /// The current state of every worker, empty until the pool runs.
+    pub fn states(&self) -> Vec<WorkerState> {
+        self.states.lock().unwrap().clone()
+    }
+
+    /// Runs the workers until `shutdown` is cancelled. Workers then stop dequeueing, and the pool waits for in-flight
+    /// items to finish for up to the shutdown timeout, after which the remaining workers are aborted.
+    pub async fn run(&self, pool: &PgPool, shutdown: CancellationToken) {
+        *self.states.lock().unwrap() = vec![WorkerState::default(); self.concurrency];
+        let wake = Arc::new(Notify::new());
+        let idle = Arc::new(Notify::new());
+
+        let mut workers = JoinSet::new();
+        for index in 0..self.concurrency {
+            let worker = Worker {
+                index,
+                process: self.process.clone(),
+                pool: pool.clone(),
+                poll: self.poll,
+                states: self.states.clone(),
+                wake: wake.clone(),
+                idle: idle.clone(),
+                shutdown: shutdown.clone(),
+            };
+            workers.spawn(worker.run());
+        }
+
+        tokio::select! {
+            () = self.dispatch(pool, &wake, &idle) => {}
+            () = shutdown.cancelled() => {}
+        }
+
+        let drain = async { while workers.join_next().await.is_some() {} };
+        if tokio::time::timeout(self.shutdown_timeout, drain)
+            .await
+            .is_err()
+        {
+            workers.abort_all();
+            while workers.join_next().await.is_some() {}
+            for state in self.states.lock().unwrap().iter_mut() {
+                if state.status == WorkerStatus::Busy {
+                    state.status = WorkerStatus::Aborted;
+                }
+            }
+        }
+    }
+
+    /// Wakes up idle workers whenever there may be work, until the future is dropped.
+    async fn dispatch(&self, pool: &PgPool, wake: &Notify, idle: &Notify) {
+        let mut listener = None;
+        loop {
+            // Waiting while all workers are busy would return immediately as long as items are due.
+            idle.notified().await;
+
+            let waited = match &mut listener {
+                Some(listener) => crate::Listener::wait_for_work(listener).await,
+                None => self.queue.listen(pool, self.poll).await.map(|connected| {
+                    listener = Some(connected);
+                }),
+            };
+            if waited.is_err() {
+                // Workers keep polling on their own until the listener is back.
+                listener = None;
+                tokio::time::sleep(self.poll).await;
+            }
+            wake.notify_waiters();
+        }
+    }
+}
+
+/// A single worker of a [`WorkerPool`].
+struct Worker {
+    index: usize,
+    process: Process,
+    pool: PgPool,
+    poll: Duration,
+    states: Arc<Mutex<Vec<WorkerState>>>,
+    wake: Arc<Notify>,
+    idle: Arc<Notify>,
+    shutdown: CancellationToken,
+}
+
+impl Worker {
+    async fn run(self) {
+        while !self.shutdown.is_cancelled() {
+            // Registered before checking the queue, so that a wakeup sent in the meantime is not lost.
+            let woken = self.wake.notified();
+            tokio::pin!(woken);
+            woken.as_mut().enable();
+
+            self.update(|state| state.status = WorkerStatus::Busy);
+            let result = (self.process)(self.pool.clone()).await;
+            self.update(|state| match &result {
+                Ok(Some(_)) => state.processed += 1,
+                Ok(None) => {}
+                Err(error) => {
+                    state.errors += 1;
+                    state.last_error = Some(error.to_string());
+                }
+            });
+            match result {
+                // Handler errors only concern a single item, so move on to the next one.
+                Ok(Some(_)) | Err(Error::Handler(_)) => continue,
+                Ok(None) | Err(_) => {}
+            }
+
+            self.update(|state| state.status = WorkerStatus::Idle);
+            self.idle.notify_one();
+            tokio::select! {
+                () = woken => {}
+                () = tokio::time::sleep(self.poll) => {}
+                () = self.shutdown.cancelled() => {}
+            }
+        }
+        self.update(|state| state.status = WorkerStatus::Stopped);
+    }
+
+    fn update(&self, f: impl FnOnce(&mut WorkerState)) {
+        f(&mut self.states.lock().unwrap()[self.index]);
+    }
+}
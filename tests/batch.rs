@@ -0,0 +1,153 @@
+//! Runs against the database at `DATABASE_URL`, with `cargo test -- --ignored`.
+
+mod common;
+
+use std::time::Duration;
+
+use pg_queue::{batch_handler_fn, CompletionPolicy, Error, Outcome, ProcessFlow, Queue};
+use sqlx::PgPool;
+
+/// The status, message and attempts of every item of `queue`, in the order they were enqueued.
+async fn items(pool: &PgPool, queue: &Queue<u64>) -> Vec<(String, Option<String>, i32)> {
+    sqlx::query_as("SELECT status::text, message, attempts FROM queue WHERE queue = $1 ORDER BY id")
+        .bind(queue.name())
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn applies_every_flow() {
+    let (pool, queue) = common::setup_with("applies_every_flow", |queue| {
+        queue.with_completion(CompletionPolicy::Archive)
+    })
+    .await;
+    let ids = queue.enqueue_many(&pool, [1, 2, 3]).await.unwrap();
+
+    let outcomes = queue
+        .process_batch(
+            &pool,
+            3,
+            batch_handler_fn(|_tx, items: &[u64]| {
+                Box::pin(async move {
+                    Ok(items
+                        .iter()
+                        .map(|item| match item {
+                            1 => ProcessFlow::Success,
+                            2 => ProcessFlow::Requeue,
+                            _ => ProcessFlow::Fail(format!("item {item}")),
+                        })
+                        .collect())
+                })
+            }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        outcomes,
+        [
+            Outcome::Success,
+            Outcome::Requeue,
+            Outcome::Fail("item 3".to_owned())
+        ]
+    );
+
+    assert_eq!(
+        items(&pool, &queue).await,
+        [
+            ("ready".to_owned(), None, 1),
+            ("failed".to_owned(), Some("item 3".to_owned()), 1)
+        ]
+    );
+    let (archived,): (i64,) = sqlx::query_as("SELECT id FROM queue_archive WHERE queue = $1")
+        .bind(queue.name())
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(archived, ids[0]);
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn fails_exhausted_and_malformed_items() {
+    let (pool, queue) = common::setup_with("fails_exhausted_and_malformed_items", |queue| {
+        queue.with_max_attempts(1)
+    })
+    .await;
+    // Dequeued on its only attempt by a worker that never settles it.
+    queue.enqueue(&pool, 1).await.unwrap();
+    queue.dequeue(&pool, Duration::ZERO).await.unwrap().unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    Queue::new(queue.name())
+        .enqueue(&pool, "two".to_owned())
+        .await
+        .unwrap();
+    queue.enqueue(&pool, 3).await.unwrap();
+
+    let outcomes = queue
+        .process_batch(
+            &pool,
+            3,
+            batch_handler_fn(|_tx, items: &[u64]| {
+                Box::pin(async move {
+                    assert_eq!(items, [3]);
+                    Ok(vec![ProcessFlow::Success])
+                })
+            }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(outcomes.len(), 3);
+    assert_eq!(
+        outcomes[0],
+        Outcome::Fail("gave up after 1 attempts".to_owned())
+    );
+    assert!(
+        matches!(&outcomes[1], Outcome::Malformed(message) if message.starts_with("unable to deserialize item")),
+        "{outcomes:?}"
+    );
+    assert_eq!(outcomes[2], Outcome::Success);
+}
+
+/// Processes a batch of two items with `f`, which fails, and checks that both items are requeued with `message`.
+async fn requeues_every_item(name: &str, f: fn(&[u64]) -> Vec<ProcessFlow>, message: &str) {
+    let (pool, queue) = common::setup::<u64>(name).await;
+    queue.enqueue_many(&pool, [1, 2]).await.unwrap();
+
+    let result = queue
+        .process_batch(
+            &pool,
+            2,
+            batch_handler_fn(move |_tx, items: &[u64]| Box::pin(async move { Ok(f(items)) })),
+        )
+        .await;
+    assert!(
+        matches!(&result, Err(Error::Handler(error)) if *error == format!("{message}, items requeued")),
+        "{result:?}"
+    );
+    let requeued = ("ready".to_owned(), Some(message.to_owned()), 1);
+    assert_eq!(items(&pool, &queue).await, [requeued.clone(), requeued]);
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn requeues_on_wrong_flow_count() {
+    requeues_every_item(
+        "requeues_on_wrong_flow_count",
+        |_items| vec![ProcessFlow::Success],
+        "handler returned 1 flows for 2 items",
+    )
+    .await;
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn requeues_on_panic() {
+    requeues_every_item(
+        "requeues_on_panic",
+        |_items| panic!("boom"),
+        "handler panicked: boom",
+    )
+    .await;
+}
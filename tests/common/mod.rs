@@ -0,0 +1,34 @@
+//! Setup shared by the tests running against the database at `DATABASE_URL`, with `cargo test -- --ignored`.
+
+// Every test file only uses some of the helpers.
+#![allow(dead_code)]
+
+use pg_queue::Queue;
+use sqlx::PgPool;
+
+pub async fn connect() -> PgPool {
+    let url =
+        std::env::var("DATABASE_URL").expect("DATABASE_URL must be set to run database tests");
+    PgPool::connect(&url).await.unwrap()
+}
+
+/// Connects to the database and migrates the queue named after `name`.
+pub async fn setup<T>(name: &str) -> (PgPool, Queue<T>) {
+    setup_with(name, |queue| queue).await
+}
+
+/// Like [`setup`], configuring the queue with `build` first.
+pub async fn setup_with<T>(
+    name: &str,
+    build: impl FnOnce(Queue<T>) -> Queue<T>,
+) -> (PgPool, Queue<T>) {
+    let pool = connect().await;
+    let queue = build(Queue::new(unique(name)));
+    queue.migrate(&pool).await.unwrap();
+    (pool, queue)
+}
+
+/// `name`, made unique to this run so that items and schedules left over by earlier runs never show up.
+pub fn unique(name: &str) -> String {
+    format!("{name}-{}", std::process::id())
+}
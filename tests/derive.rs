@@ -0,0 +1,40 @@
+//! Runs with the `derive` feature, e.g. `cargo test --all-features`.
+#![cfg(feature = "derive")]
+
+use std::time::Duration;
+
+use pg_queue::{Job, Queue, RetryPolicy};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Job)]
+#[job(kind = "send_email", queue = "emails", priority = 10, max_attempts = 3)]
+#[job(retry = RetryPolicy::Fixed(Duration::from_secs(60)), timeout = Duration::from_secs(30))]
+struct SendEmail {
+    address: String,
+}
+
+#[derive(Serialize, Deserialize, Job)]
+struct Payout(u64);
+
+#[test]
+fn job_queue_takes_attributes() {
+    let queue = Queue::<SendEmail>::job();
+    assert_eq!(queue.kind(), Some("send_email"));
+    assert_eq!(queue.name(), "emails");
+    assert_eq!(queue.priority(), 10);
+    assert_eq!(queue.max_attempts(), 3);
+    assert!(matches!(queue.retry(), RetryPolicy::Fixed(delay) if delay == Duration::from_secs(60)));
+    assert_eq!(queue.timeout(), Some(Duration::from_secs(30)));
+}
+
+#[test]
+fn job_queue_defaults() {
+    let queue = Queue::<Payout>::job();
+    let defaults = Queue::<Payout>::new("default");
+    assert_eq!(queue.kind(), Some("Payout"));
+    assert_eq!(queue.name(), "default");
+    assert_eq!(queue.priority(), defaults.priority());
+    assert_eq!(queue.max_attempts(), defaults.max_attempts());
+    assert_eq!(queue.retry().delay(3), defaults.retry().delay(3));
+    assert_eq!(queue.timeout(), None);
+}
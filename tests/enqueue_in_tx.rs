@@ -0,0 +1,46 @@
+//! Runs against the database at `DATABASE_URL`, with `cargo test -- --ignored`.
+
+mod common;
+
+use pg_queue::{handler_fn, Outcome, ProcessFlow, Queue};
+use sqlx::PgPool;
+
+async fn process(pool: &PgPool, queue: &Queue<u64>) -> Option<Outcome> {
+    queue
+        .process(
+            pool,
+            handler_fn(|_tx, _item: u64| Box::pin(async { Ok(ProcessFlow::Success) })),
+        )
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn visible_after_commit() {
+    let (pool, queue) = common::setup("visible_after_commit").await;
+
+    let mut tx = pool.begin().await.unwrap();
+    queue.enqueue_in_tx(&mut tx, 1).await.unwrap();
+    assert!(process(&pool, &queue).await.is_none());
+
+    tx.commit().await.unwrap();
+    assert!(matches!(
+        process(&pool, &queue).await,
+        Some(Outcome::Success)
+    ));
+    assert!(process(&pool, &queue).await.is_none());
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn gone_after_rollback() {
+    let (pool, queue) = common::setup("gone_after_rollback").await;
+
+    let mut tx = pool.begin().await.unwrap();
+    queue.enqueue_in_tx(&mut tx, 1).await.unwrap();
+    assert!(process(&pool, &queue).await.is_none());
+
+    tx.rollback().await.unwrap();
+    assert!(process(&pool, &queue).await.is_none());
+}
@@ -0,0 +1,91 @@
+//! Runs against the database at `DATABASE_URL`, with `cargo test -- --ignored`.
+
+mod common;
+
+use std::time::Duration;
+
+use pg_queue::{EnqueueOptions, COPY_THRESHOLD};
+
+/// Enqueues `n` items on a queue with non-default settings, and checks that every item got them, in order.
+async fn enqueue_with_defaults(name: &str, n: usize) {
+    let (pool, queue) =
+        common::setup_with(name, |queue| queue.with_priority(7).with_max_attempts(2)).await;
+    let ids = queue.enqueue_many(&pool, 0..n as u64).await.unwrap();
+    assert_eq!(ids.len(), n);
+
+    let items: Vec<(i64, serde_json::Value)> = sqlx::query_as(
+        "
+        SELECT id, item
+        FROM queue
+        WHERE queue = $1 AND priority = 7 AND max_attempts = 2 AND status = 'ready'
+        ORDER BY id",
+    )
+    .bind(queue.name())
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    let expected: Vec<_> = ids
+        .into_iter()
+        .zip(0..n as u64)
+        .map(|(id, item)| (id, item.into()))
+        .collect();
+    assert_eq!(items, expected);
+}
+
+/// Enqueues `n` items with options, and checks that every item got them.
+async fn enqueue_with_options(name: &str, n: usize) {
+    let (pool, queue) = common::setup::<u64>(name).await;
+    let options = EnqueueOptions {
+        priority: Some(7),
+        max_attempts: Some(2),
+        delay: Duration::from_secs(3600),
+        timeout: Some(Duration::from_millis(1500)),
+        ..EnqueueOptions::default()
+    };
+    let ids = queue
+        .enqueue_many_with(&pool, 0..n as u64, options)
+        .await
+        .unwrap();
+    assert_eq!(ids.len(), n);
+
+    let (count, distinct): (i64, i64) = sqlx::query_as(
+        "
+        SELECT count(*), count(DISTINCT (priority, max_attempts, timeout, scheduled_at, run_after))
+        FROM queue
+        WHERE queue = $1
+          AND priority = 7
+          AND max_attempts = 2
+          AND timeout = interval '1.5 seconds'
+          AND run_after > now() + interval '59 minutes'
+          AND run_after = scheduled_at",
+    )
+    .bind(queue.name())
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!((count, distinct), (n as i64, 1));
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn insert_with_defaults() {
+    enqueue_with_defaults("insert_with_defaults", 10).await;
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn copy_with_defaults() {
+    enqueue_with_defaults("copy_with_defaults", COPY_THRESHOLD + 1).await;
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn insert_with_options() {
+    enqueue_with_options("insert_with_options", 10).await;
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn copy_with_options() {
+    enqueue_with_options("copy_with_options", COPY_THRESHOLD + 1).await;
+}
@@ -0,0 +1,56 @@
+//! Runs against the database at `DATABASE_URL`, with `cargo test -- --ignored`.
+
+mod common;
+
+use pg_queue::{handler_fn, Job, Outcome, ProcessFlow, Queue, Registry};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct SendEmail(String);
+
+impl Job for SendEmail {
+    const KIND: &'static str = "send_email";
+}
+
+#[derive(Serialize, Deserialize)]
+struct Payout(u64);
+
+impl Job for Payout {
+    const KIND: &'static str = "payout";
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn untyped_queue_routes_by_kind() {
+    let (pool, queue): (_, Queue) = common::setup("untyped_queue_routes_by_kind").await;
+
+    queue
+        .enqueue_job(&pool, SendEmail("a@example.com".to_owned()))
+        .await
+        .unwrap();
+    queue.enqueue_job(&pool, Payout(10)).await.unwrap();
+    queue.enqueue(&pool, 1.into()).await.unwrap();
+
+    let registry = Registry::new()
+        .register::<SendEmail, _>(handler_fn(|_tx, email: SendEmail| {
+            Box::pin(async move {
+                assert_eq!(email.0, "a@example.com");
+                Ok(ProcessFlow::Success)
+            })
+        }))
+        .register::<Payout, _>(handler_fn(|_tx, payout: Payout| {
+            Box::pin(async move { Ok(ProcessFlow::Fail(format!("payout of {}", payout.0))) })
+        }));
+    let mut outcomes = Vec::new();
+    while let Some(outcome) = queue.process_jobs(&pool, &registry).await.unwrap() {
+        outcomes.push(outcome);
+    }
+    assert_eq!(
+        outcomes,
+        [
+            Outcome::Success,
+            Outcome::Fail("payout of 10".to_owned()),
+            Outcome::Malformed("item has no kind, it was not enqueued as a job".to_owned()),
+        ]
+    );
+}
@@ -0,0 +1,99 @@
+//! Runs against the database at `DATABASE_URL`, with `cargo test -- --ignored`.
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use pg_queue::{
+    handler_fn, Error, Job, Layer, MetricsLayer, ProcessFlow, Queue, Registry, TimeoutLayer,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+async fn setup(name: &str) -> (PgPool, Queue<u64>) {
+    let (pool, queue) = common::setup(name).await;
+    queue.enqueue(&pool, 1).await.unwrap();
+    (pool, queue)
+}
+
+/// A metrics layer recording whether every attempt succeeded.
+fn metrics() -> (MetricsLayer, Arc<Mutex<Vec<bool>>>) {
+    let recorded = Arc::new(Mutex::new(Vec::new()));
+    let layer = MetricsLayer::new({
+        let recorded = recorded.clone();
+        move |result, _elapsed| recorded.lock().unwrap().push(result.is_ok())
+    });
+    (layer, recorded)
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn timeout_layer_abandons_attempt() {
+    let (pool, queue) = setup("timeout_layer_abandons_attempt").await;
+    let (metrics, recorded) = metrics();
+    let handler = handler_fn(|_tx, _item: u64| {
+        Box::pin(async {
+            tokio::time::sleep(Duration::from_secs(4)).await;
+            Ok(ProcessFlow::Success)
+        })
+    });
+    let handler = metrics.layer(TimeoutLayer::new(Duration::from_millis(100)).layer(handler));
+
+    let started = Instant::now();
+    let result = queue.process(&pool, handler).await;
+    assert!(
+        matches!(&result, Err(Error::Handler(message)) if message.starts_with("handler timed out after 100ms")),
+        "{result:?}"
+    );
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(*recorded.lock().unwrap(), [false]);
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn metrics_record_panics() {
+    let (pool, queue) = setup("metrics_record_panics").await;
+    let (metrics, recorded) = metrics();
+    let handler = metrics.layer(handler_fn(|_tx, _item: u64| {
+        Box::pin(async { panic!("boom") })
+    }));
+
+    let result = queue.process(&pool, handler).await;
+    assert!(
+        matches!(&result, Err(Error::Handler(message)) if message.starts_with("handler panicked: boom")),
+        "{result:?}"
+    );
+    assert_eq!(*recorded.lock().unwrap(), [false]);
+}
+
+#[derive(Serialize, Deserialize)]
+struct Slow;
+
+impl Job for Slow {
+    const KIND: &'static str = "slow";
+    const TIMEOUT: Option<Duration> = Some(Duration::from_secs(3));
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn timeout_layer_overrides_job_timeout() {
+    let (pool, queue): (_, Queue) = common::setup("timeout_layer_overrides_job_timeout").await;
+    queue.enqueue_job(&pool, Slow).await.unwrap();
+    let handler = handler_fn(|_tx, _job: Slow| {
+        Box::pin(async {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            Ok(ProcessFlow::Success)
+        })
+    });
+    let registry = Registry::new()
+        .register::<Slow, _>(TimeoutLayer::new(Duration::from_millis(100)).layer(handler));
+
+    let started = Instant::now();
+    let result = queue.process_jobs(&pool, &registry).await;
+    assert!(
+        matches!(&result, Err(Error::Handler(message)) if message.starts_with("handler timed out after 100ms")),
+        "{result:?}"
+    );
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
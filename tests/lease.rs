@@ -0,0 +1,85 @@
+//! Runs against the database at `DATABASE_URL`, with `cargo test -- --ignored`.
+
+mod common;
+
+use std::time::Duration;
+
+use pg_queue::{handler_fn, Outcome, ProcessFlow};
+use tokio::sync::oneshot;
+
+const LEASE: Duration = Duration::from_secs(60);
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn expired_lease_cannot_settle() {
+    let (pool, queue) = common::setup::<u64>("expired_lease_cannot_settle").await;
+    queue.enqueue(&pool, 1).await.unwrap();
+
+    let expired = queue.dequeue(&pool, Duration::ZERO).await.unwrap().unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    let current = queue.dequeue(&pool, LEASE).await.unwrap().unwrap();
+    assert_eq!((current.id, current.attempts), (expired.id, 2));
+
+    assert!(!queue.ack(&pool, &expired).await.unwrap());
+    assert!(!queue.heartbeat(&pool, &expired, LEASE).await.unwrap());
+    assert!(!queue.nack(&pool, &expired).await.unwrap());
+    assert!(!queue.fail(&pool, &expired, "expired").await.unwrap());
+
+    assert!(queue.heartbeat(&pool, &current, LEASE).await.unwrap());
+    assert!(queue.ack(&pool, &current).await.unwrap());
+    // Settling is final, even for the current lease.
+    assert!(!queue.nack(&pool, &current).await.unwrap());
+
+    let (status,): (String,) = sqlx::query_as("SELECT status::text FROM queue WHERE id = $1")
+        .bind(current.id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "completed");
+}
+
+/// Waits for the 30 second lease of `Queue::process` to expire, which cannot be shortened.
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn locked_item_is_not_reclaimed() {
+    let (pool, queue) = common::setup::<u64>("locked_item_is_not_reclaimed").await;
+    let id = queue.enqueue(&pool, 1).await.unwrap();
+
+    let (started, handling) = oneshot::channel();
+    let (finish, finished) = oneshot::channel::<()>();
+    let processing = tokio::spawn({
+        let (pool, queue) = (pool.clone(), queue.clone());
+        async move {
+            let handler = handler_fn(move |_tx, _item: u64| {
+                Box::pin(async move {
+                    started.send(()).unwrap();
+                    finished.await.unwrap();
+                    Ok(ProcessFlow::Success)
+                })
+            });
+            queue.process(&pool, handler).await.unwrap()
+        }
+    });
+    handling.await.unwrap();
+
+    tokio::time::sleep(Duration::from_secs(31)).await;
+    let (expired,): (bool,) =
+        sqlx::query_as("SELECT locked_until < now() FROM queue WHERE id = $1")
+            .bind(id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert!(expired);
+    // The processing transaction holds the lock on the item, so it is skipped despite its expired lease.
+    assert!(queue.dequeue(&pool, LEASE).await.unwrap().is_none());
+
+    finish.send(()).unwrap();
+    assert_eq!(processing.await.unwrap(), Some(Outcome::Success));
+    let (status, attempts): (String, i32) =
+        sqlx::query_as("SELECT status::text, attempts FROM queue WHERE id = $1")
+            .bind(id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!((status.as_str(), attempts), ("completed", 1));
+}
@@ -0,0 +1,40 @@
+//! Runs against the database at `DATABASE_URL`, with `cargo test -- --ignored`.
+
+mod common;
+
+use pg_queue::{handler_fn, pending_migrations, Outcome, ProcessFlow, Queue, QueueConfig};
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn quoted_names() {
+    let pool = common::connect().await;
+    // Names are unique to every run, so that every run starts from an empty schema.
+    let config = QueueConfig {
+        schema: format!("o'neil %s \"{}\"", std::process::id()),
+        prefix: "a'b%_".to_owned(),
+    };
+    let queue = Queue::with_config("quoted", config.clone());
+
+    queue.migrate(&pool).await.unwrap();
+    assert!(pending_migrations(&pool, &config).await.unwrap().is_empty());
+    // Applying the migrations again is a no-op.
+    queue.migrate(&pool).await.unwrap();
+
+    queue.enqueue(&pool, 1u64).await.unwrap();
+    let outcome = queue
+        .process(
+            &pool,
+            handler_fn(|_tx, _item: u64| Box::pin(async { Ok(ProcessFlow::Success) })),
+        )
+        .await
+        .unwrap();
+    assert_eq!(outcome, Some(Outcome::Success));
+
+    sqlx::query(&format!(
+        "DROP SCHEMA \"{}\" CASCADE",
+        config.schema.replace('"', "\"\"")
+    ))
+    .execute(&pool)
+    .await
+    .unwrap();
+}
@@ -0,0 +1,57 @@
+//! Runs against the database at `DATABASE_URL`, with `cargo test -- --ignored`.
+
+mod common;
+
+use std::time::Duration;
+
+use chrono::Utc;
+use pg_queue::{EnqueueOptions, Queue};
+use sqlx::PgPool;
+
+/// Enqueues `item` with `priority`, due `ago` seconds ago.
+async fn enqueue(pool: &PgPool, queue: &Queue<u64>, item: u64, priority: i32, ago: i64) {
+    let options = EnqueueOptions {
+        priority: Some(priority),
+        at: Some(Utc::now() - chrono::Duration::seconds(ago)),
+        ..EnqueueOptions::default()
+    };
+    queue.enqueue_with(pool, item, options).await.unwrap();
+}
+
+/// Dequeues every item of `queue`, in order.
+async fn dequeue_all(pool: &PgPool, queue: &Queue<u64>) -> Vec<u64> {
+    let mut items = Vec::new();
+    while let Some(leased) = queue.dequeue(pool, Duration::from_secs(60)).await.unwrap() {
+        items.push(leased.item);
+    }
+    items
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn dequeues_by_priority_then_due_time() {
+    let (pool, queue) = common::setup("dequeues_by_priority_then_due_time").await;
+    enqueue(&pool, &queue, 1, 0, 3).await;
+    enqueue(&pool, &queue, 2, 5, 1).await;
+    enqueue(&pool, &queue, 3, 5, 2).await;
+    enqueue(&pool, &queue, 4, 0, 4).await;
+    enqueue(&pool, &queue, 5, -1, 10).await;
+
+    assert_eq!(dequeue_all(&pool, &queue).await, [3, 2, 4, 1, 5]);
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn aging_overtakes_higher_priorities() {
+    let (pool, queue) = common::setup_with("aging_overtakes_higher_priorities", |queue| {
+        queue.with_aging(Duration::from_secs(1))
+    })
+    .await;
+    // Aged by 10, ahead of the newer item by 5.
+    enqueue(&pool, &queue, 1, 0, 10).await;
+    enqueue(&pool, &queue, 2, 5, 0).await;
+    // Aged by 2, still behind.
+    enqueue(&pool, &queue, 3, 0, 2).await;
+
+    assert_eq!(dequeue_all(&pool, &queue).await, [1, 2, 3]);
+}
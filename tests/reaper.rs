@@ -0,0 +1,42 @@
+//! Runs against the database at `DATABASE_URL`, with `cargo test -- --ignored`.
+
+mod common;
+
+use std::time::Duration;
+
+use pg_queue::{Queue, QueueConfig, Reaper};
+use sqlx::PgPool;
+
+/// Dequeues the next item with a lease that expires immediately, and reaps it.
+async fn strand(pool: &PgPool, queue: &Queue<u64>) -> (String, Option<String>, i32) {
+    let leased = queue.dequeue(pool, Duration::ZERO).await.unwrap().unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    let reaper = Reaper::new(QueueConfig::default(), Duration::from_secs(1));
+    assert!(reaper.reap(pool).await.unwrap() >= 1);
+
+    sqlx::query_as("SELECT status::text, message, attempts FROM queue WHERE id = $1")
+        .bind(leased.id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn keeps_lease_expired_message() {
+    let (pool, queue) = common::setup_with("keeps_lease_expired_message", |queue| {
+        queue.with_max_attempts(2)
+    })
+    .await;
+    queue.enqueue(&pool, 1).await.unwrap();
+
+    let reaped = strand(&pool, &queue).await;
+    assert_eq!(
+        reaped,
+        ("ready".to_owned(), Some("lease expired".to_owned()), 1)
+    );
+
+    let reaped = strand(&pool, &queue).await;
+    let message = "gave up after 2 attempts, lease expired".to_owned();
+    assert_eq!(reaped, ("failed".to_owned(), Some(message), 2));
+}
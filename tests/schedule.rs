@@ -0,0 +1,120 @@
+//! Runs against the database at `DATABASE_URL`, with `cargo test -- --ignored`.
+
+mod common;
+
+use chrono_tz::Tz;
+use std::time::Duration;
+
+use pg_queue::{Misfire, Queue, QueueConfig, Schedule, Scheduler};
+
+fn nightly(item: u64) -> Schedule<u64> {
+    Schedule {
+        name: "nightly".to_owned(),
+        cron: "0 0 3 * * *".to_owned(),
+        timezone: Tz::UTC,
+        item,
+        misfire: Misfire::Skip,
+        timeout: None,
+    }
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn names_are_per_queue() {
+    let (pool, first) = common::setup("names_are_per_queue_a").await;
+    let second = Queue::<u64>::new(common::unique("names_are_per_queue_b"));
+
+    first.schedule(&pool, nightly(1)).await.unwrap();
+    second.schedule(&pool, nightly(2)).await.unwrap();
+    // Replaces the first queue's schedule only.
+    first.schedule(&pool, nightly(3)).await.unwrap();
+
+    let items: Vec<(String, serde_json::Value)> =
+        sqlx::query_as("SELECT queue, item FROM schedules WHERE queue = ANY($1) ORDER BY item")
+            .bind([first.name(), second.name()])
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        items,
+        [
+            (second.name().to_owned(), 2.into()),
+            (first.name().to_owned(), 3.into())
+        ]
+    );
+
+    assert!(first.unschedule(&pool, "nightly").await.unwrap());
+    assert!(!first.unschedule(&pool, "nightly").await.unwrap());
+    assert!(second.unschedule(&pool, "nightly").await.unwrap());
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn enqueues_with_timeout() {
+    let (pool, queue) = common::setup("enqueues_with_timeout").await;
+    let schedule = Schedule {
+        name: "every_second".to_owned(),
+        cron: "* * * * * *".to_owned(),
+        timeout: Some(Duration::from_millis(1500)),
+        ..nightly(1)
+    };
+    queue.schedule(&pool, schedule).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let scheduler = Scheduler::new(QueueConfig::default(), Duration::from_secs(1));
+    assert!(scheduler.tick(&pool).await.unwrap() >= 1);
+    queue.unschedule(&pool, "every_second").await.unwrap();
+
+    let (timeout,): (f64,) = sqlx::query_as(
+        "SELECT extract(epoch FROM timeout)::float8 FROM queue WHERE queue = $1 LIMIT 1",
+    )
+    .bind(queue.name())
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(timeout, 1.5);
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn keeps_missed_occurrences_when_rescheduled() {
+    let (pool, queue) = common::setup("keeps_missed_occurrences_when_rescheduled").await;
+    let hourly = Schedule {
+        name: "hourly".to_owned(),
+        cron: "0 0 * * * *".to_owned(),
+        misfire: Misfire::RunAll,
+        ..nightly(1)
+    };
+    queue.schedule(&pool, hourly.clone()).await.unwrap();
+    // Three occurrences are missed while the application is down, which registers its schedules again on startup.
+    sqlx::query(
+        "
+        UPDATE schedules
+        SET next_run_at = date_trunc('hour', now()) - interval '2 hours'
+        WHERE queue = $1 AND name = 'hourly'",
+    )
+    .bind(queue.name())
+    .execute(&pool)
+    .await
+    .unwrap();
+    queue.schedule(&pool, hourly.clone()).await.unwrap();
+
+    let scheduler = Scheduler::new(QueueConfig::default(), Duration::from_secs(1));
+    assert_eq!(scheduler.tick(&pool).await.unwrap(), 3);
+
+    // Changing the cron expression starts over from the next occurrence of the new one.
+    sqlx::query(
+        "UPDATE schedules SET next_run_at = now() - interval '3 hours' WHERE queue = $1 AND name = 'hourly'",
+    )
+    .bind(queue.name())
+    .execute(&pool)
+    .await
+    .unwrap();
+    let half_hourly = Schedule {
+        cron: "0 */30 * * * *".to_owned(),
+        ..hourly
+    };
+    queue.schedule(&pool, half_hourly).await.unwrap();
+    assert_eq!(scheduler.tick(&pool).await.unwrap(), 0);
+    queue.unschedule(&pool, "hourly").await.unwrap();
+}
@@ -0,0 +1,150 @@
+//! Runs against the database at `DATABASE_URL`, with `cargo test -- --ignored`.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use pg_queue::{batch_handler_fn, handler_fn, Error, Outcome, ProcessFlow, Queue};
+use sqlx::PgPool;
+
+const TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Processes the next item with a handler running `sql`, returning the result and how long processing took.
+async fn process(
+    pool: &PgPool,
+    queue: &Queue<u64>,
+    sql: &'static str,
+) -> (Result<Option<Outcome>, Error>, Duration) {
+    let started = Instant::now();
+    let result = queue
+        .process(
+            pool,
+            handler_fn(move |tx, _item: u64| {
+                Box::pin(async move {
+                    sqlx::query(sql).execute(&mut **tx).await.map_err(|_| ())?;
+                    Ok(ProcessFlow::Success)
+                })
+            }),
+        )
+        .await;
+    (result, started.elapsed())
+}
+
+fn assert_timed_out(result: Result<Option<Outcome>, Error>, elapsed: Duration) {
+    assert!(
+        matches!(&result, Err(Error::Handler(message)) if message.starts_with("handler timed out")),
+        "{result:?}"
+    );
+    assert!(
+        elapsed < 2 * TIMEOUT + Duration::from_millis(300),
+        "{elapsed:?}"
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn cancels_running_statement() {
+    let (pool, queue) = common::setup_with("cancels_running_statement", |queue| {
+        queue.with_timeout(TIMEOUT)
+    })
+    .await;
+    queue.enqueue(&pool, 1).await.unwrap();
+
+    let (result, elapsed) = process(&pool, &queue, "SELECT pg_sleep(4)").await;
+    assert_timed_out(result, elapsed);
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn cancels_lock_wait() {
+    let (pool, queue) =
+        common::setup_with("cancels_lock_wait", |queue| queue.with_timeout(TIMEOUT)).await;
+    queue.enqueue(&pool, 1).await.unwrap();
+
+    let key = std::process::id() as i64;
+    let mut holder = pool.acquire().await.unwrap();
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(key)
+        .execute(&mut *holder)
+        .await
+        .unwrap();
+
+    let started = Instant::now();
+    let result = queue
+        .process(
+            &pool,
+            handler_fn(move |tx, _item: u64| {
+                Box::pin(async move {
+                    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+                        .bind(key)
+                        .execute(&mut **tx)
+                        .await
+                        .map_err(|_| ())?;
+                    Ok(ProcessFlow::Success)
+                })
+            }),
+        )
+        .await;
+    assert_timed_out(result, started.elapsed());
+
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(key)
+        .execute(&mut *holder)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn cancels_running_batch_statement() {
+    let (pool, queue) = common::setup_with("cancels_running_batch_statement", |queue| {
+        queue.with_timeout(TIMEOUT)
+    })
+    .await;
+    queue.enqueue_many(&pool, [1, 2]).await.unwrap();
+
+    let started = Instant::now();
+    let result = queue
+        .process_batch(
+            &pool,
+            2,
+            batch_handler_fn(|tx, items: &[u64]| {
+                Box::pin(async move {
+                    sqlx::query("SELECT pg_sleep(4)")
+                        .execute(&mut **tx)
+                        .await
+                        .map_err(|_| ())?;
+                    Ok(vec![ProcessFlow::Success; items.len()])
+                })
+            }),
+        )
+        .await;
+    let elapsed = started.elapsed();
+    assert!(
+        matches!(&result, Err(Error::Handler(message)) if message.starts_with("handler timed out")),
+        "{result:?}"
+    );
+    assert!(
+        elapsed < 2 * TIMEOUT + Duration::from_millis(300),
+        "{elapsed:?}"
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn completes_within_timeout() {
+    let (pool, queue) = common::setup_with("completes_within_timeout", |queue| {
+        queue.with_timeout(TIMEOUT)
+    })
+    .await;
+    queue.enqueue(&pool, 1).await.unwrap();
+
+    let (result, _) = process(&pool, &queue, "SELECT pg_sleep(0.01)").await;
+    assert_eq!(result.unwrap(), Some(Outcome::Success));
+    // The statement timeout only applies to the handler's statements, and ends with the processing transaction.
+    let (timeout,): (String,) = sqlx::query_as("SHOW statement_timeout")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(timeout, "0");
+}
@@ -0,0 +1,136 @@
+//! Runs against the database at `DATABASE_URL`, with `cargo test -- --ignored`.
+
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use pg_queue::{
+    handler_fn, CancellationToken, ProcessFlow, Queue, WorkerPool, WorkerState, WorkerStatus,
+};
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+
+/// Runs `workers` until the returned token is cancelled, returning the token and the running pool.
+fn run(
+    workers: &Arc<WorkerPool<u64>>,
+    pool: &PgPool,
+) -> (CancellationToken, tokio::task::JoinHandle<()>) {
+    let shutdown = CancellationToken::new();
+    let running = tokio::spawn({
+        let (workers, pool, shutdown) = (workers.clone(), pool.clone(), shutdown.clone());
+        async move { workers.run(&pool, shutdown).await }
+    });
+    (shutdown, running)
+}
+
+/// A pool running a handler that reports every item it starts, then sleeps for `duration`.
+fn sleeping(
+    queue: &Queue<u64>,
+    duration: Duration,
+) -> (WorkerPool<u64>, mpsc::UnboundedReceiver<u64>) {
+    let (started, starts) = mpsc::unbounded_channel();
+    let handler = handler_fn(move |_tx, item: u64| {
+        Box::pin(async move {
+            started.send(item).unwrap();
+            tokio::time::sleep(duration).await;
+            Ok(ProcessFlow::Success)
+        })
+    });
+    (WorkerPool::new(queue.clone(), handler), starts)
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn finishes_in_flight_items_on_shutdown() {
+    let (pool, queue) = common::setup::<u64>("finishes_in_flight_items_on_shutdown").await;
+    let (workers, mut starts) = sleeping(&queue, Duration::from_millis(300));
+    let workers = Arc::new(workers.with_concurrency(2));
+    queue.enqueue(&pool, 1).await.unwrap();
+
+    let (shutdown, running) = run(&workers, &pool);
+    assert_eq!(starts.recv().await, Some(1));
+    shutdown.cancel();
+    running.await.unwrap();
+
+    let (status,): (String,) = sqlx::query_as("SELECT status::text FROM queue WHERE queue = $1")
+        .bind(queue.name())
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "completed");
+    let processed: u64 = workers.states().iter().map(|state| state.processed).sum();
+    assert_eq!(processed, 1);
+    assert!(workers
+        .states()
+        .iter()
+        .all(|state| state.status == WorkerStatus::Stopped));
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn aborts_stuck_workers_after_shutdown_timeout() {
+    let (pool, queue) = common::setup::<u64>("aborts_stuck_workers_after_shutdown_timeout").await;
+    let (workers, mut starts) = sleeping(&queue, Duration::from_secs(60));
+    let workers = Arc::new(workers.with_shutdown_timeout(Duration::from_millis(500)));
+    let id = queue.enqueue(&pool, 1).await.unwrap();
+
+    let (shutdown, running) = run(&workers, &pool);
+    assert_eq!(starts.recv().await, Some(1));
+    let cancelled = Instant::now();
+    shutdown.cancel();
+    running.await.unwrap();
+    assert!(
+        cancelled.elapsed() < Duration::from_secs(2),
+        "{:?}",
+        cancelled.elapsed()
+    );
+    assert_eq!(
+        workers.states(),
+        [WorkerState {
+            status: WorkerStatus::Aborted,
+            ..WorkerState::default()
+        }]
+    );
+
+    // The aborted attempt rolled back, so the item is dequeued again once its lease expires, brought forward here.
+    sqlx::query("UPDATE queue SET locked_until = now() - interval '1 second' WHERE id = $1")
+        .bind(id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let leased = queue
+        .dequeue(&pool, Duration::from_secs(60))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!((leased.id, leased.attempts), (id, 2));
+}
+
+#[tokio::test]
+#[ignore = "requires DATABASE_URL"]
+async fn wakes_up_on_notify() {
+    let (pool, queue) = common::setup::<u64>("wakes_up_on_notify").await;
+    let (workers, mut starts) = sleeping(&queue, Duration::ZERO);
+    // Idle workers only check the queue every minute unless they are notified.
+    let workers = Arc::new(workers.with_poll(Duration::from_secs(60)));
+
+    let (shutdown, running) = run(&workers, &pool);
+    while workers.states().first().map(|state| state.status) != Some(WorkerStatus::Idle) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    // Gives the pool's listener time to connect.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let enqueued = Instant::now();
+    queue.enqueue(&pool, 1).await.unwrap();
+    assert_eq!(starts.recv().await, Some(1));
+    assert!(
+        enqueued.elapsed() < Duration::from_secs(2),
+        "{:?}",
+        enqueued.elapsed()
+    );
+
+    shutdown.cancel();
+    running.await.unwrap();
+}